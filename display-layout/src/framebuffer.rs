@@ -0,0 +1,113 @@
+use std::convert::Infallible;
+
+use display_payload::DisplayModel;
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+
+use crate::profile;
+
+/// A 1-bit framebuffer with the same memory layout as the epd-waveshare display buffers,
+/// so that it can be sent as is to the panel.
+/// `BinaryColor::On` is black, `BinaryColor::Off` is white.
+pub struct Framebuffer {
+    model: DisplayModel,
+    buffer: Vec<u8>,
+}
+
+impl Framebuffer {
+    pub fn new(model: DisplayModel) -> Self {
+        Self {
+            model,
+            // All white
+            buffer: vec![0xFF; model.buffer_len()],
+        }
+    }
+
+    /// Wraps a buffer already rendered for the panel (eg. a framebuffer rendered by the server), to draw over it.
+    /// Returns None if it doesn't have the size expected by the panel
+    pub fn from_buffer(model: DisplayModel, buffer: Vec<u8>) -> Option<Self> {
+        (buffer.len() == model.buffer_len()).then_some(Self { model, buffer })
+    }
+
+    pub fn model(&self) -> DisplayModel {
+        self.model
+    }
+
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    pub fn into_buffer(self) -> Vec<u8> {
+        self.buffer
+    }
+
+    /// Byte index and bit mask of a point, or None if it's outside of the screen
+    fn position(&self, point: Point) -> Option<(usize, u8)> {
+        let size = self.size();
+        if point.x < 0 || point.y < 0 || point.x >= size.width as i32 || point.y >= size.height as i32 {
+            return None;
+        }
+        let (native_width, _) = self.model.native_size();
+        // Same mapping as epd-waveshare's DisplayRotation::Rotate90
+        let (x, y) = if profile(self.model).rotated() {
+            (native_width - 1 - point.y as u32, point.x as u32)
+        } else {
+            (point.x as u32, point.y as u32)
+        };
+        let index = (x / 8 + native_width.div_ceil(8) * y) as usize;
+        Some((index, 0x80 >> (x % 8)))
+    }
+
+    pub fn pixel(&self, point: Point) -> Option<BinaryColor> {
+        self.position(point).map(|(index, bit)| {
+            if self.buffer[index] & bit == 0 {
+                BinaryColor::On
+            } else {
+                BinaryColor::Off
+            }
+        })
+    }
+
+    /// A hash of each row of the screen (as seen by a person), to tell which rows changed between two framebuffers
+    pub fn row_hashes(&self) -> Vec<u32> {
+        let size = self.size();
+        (0..size.height as i32)
+            .map(|y| {
+                (0..size.width as i32).fold(0x811c_9dc5, |hash: u32, x| {
+                    let black = self.pixel(Point::new(x, y)) == Some(BinaryColor::On);
+                    (hash ^ black as u32).wrapping_mul(0x0100_0193)
+                })
+            })
+            .collect()
+    }
+}
+
+impl OriginDimensions for Framebuffer {
+    fn size(&self) -> Size {
+        screen_size(self.model)
+    }
+}
+
+/// Size of the screen of a display model, as seen by a person (already rotated)
+pub fn screen_size(model: DisplayModel) -> Size {
+    profile(model).size()
+}
+
+impl DrawTarget for Framebuffer {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if let Some((index, bit)) = self.position(point) {
+                match color {
+                    BinaryColor::On => self.buffer[index] &= !bit,
+                    BinaryColor::Off => self.buffer[index] |= bit,
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,398 @@
+use display_payload::{DisplayModel, Envelope, SEvent, TopicKind};
+use embedded_graphics::{
+    mono_font::{MonoFont, MonoTextStyleBuilder},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+
+use std::ops::Range;
+
+mod framebuffer;
+pub mod profile;
+pub mod text;
+
+pub use framebuffer::{screen_size, Framebuffer};
+pub use profile::{profile, Profile};
+
+/// Font and spacing used to lay out the screen of a display model, see [`Profile::style`].
+/// The fonts cover Latin-1, for the accented letters of Italian: the text is passed through [`text::transliterate`]
+/// before being drawn.
+pub struct Style {
+    pub font: &'static MonoFont<'static>,
+    /// Vertical space taken by the header (room and building names)
+    pub header_height: i32,
+    /// How many rows a long title can wrap on before being cut with an ellipsis
+    pub title_lines: usize,
+    /// Whether the last row is kept free for [`draw_status_bar`]: the footer moves one row up
+    pub status_bar: bool,
+}
+
+impl Style {
+    pub fn for_model(model: DisplayModel) -> Self {
+        profile(model).style()
+    }
+
+    fn row_height(&self) -> i32 {
+        self.font.character_size.height as i32
+    }
+
+    /// Top of the footer ("pagina 1/3", "... e altri 2"), above the status bar if any
+    fn footer_y(&self, height: i32) -> i32 {
+        height - self.row_height() * (1 + self.status_bar as i32)
+    }
+
+    /// How many characters fit in `width` pixels
+    fn chars_in(&self, width: i32) -> usize {
+        let advance = (self.font.character_size.width + self.font.character_spacing) as i32;
+        (width / advance).max(0) as usize
+    }
+}
+
+/// The lines of an event on the screen: the title, wrapped to leave room for the date
+struct EventRows {
+    /// On building screens, the room of the event: the events of each room are grouped under its name
+    room: Option<String>,
+    title: Vec<String>,
+    datetime: String,
+}
+
+impl EventRows {
+    /// Whether the event is drawn under a new room header: it's the first of its room on the page. `previous` is the
+    /// event before it on the page, if any
+    fn opens_group(&self, previous: Option<&EventRows>) -> bool {
+        self.room.is_some() && previous.map(|previous| &previous.room) != Some(&self.room)
+    }
+
+    /// Rows taken on the screen, including the room header if it opens a group
+    fn height(&self, previous: Option<&EventRows>) -> usize {
+        self.title.len().max(1) + self.opens_group(previous) as usize
+    }
+}
+
+/// The events in the order they're shown: by time on a room screen, grouped by room on a building screen (the rooms
+/// ordered by their next event, the events of each room by time)
+fn ordered_events(envelope: &Envelope) -> Vec<&SEvent> {
+    let mut events: Vec<&SEvent> = envelope.events.iter().collect();
+    if envelope.topic_kind == TopicKind::Building {
+        let mut rooms: Vec<&str> = Vec::new();
+        for event in &events {
+            if !rooms.contains(&event.room.as_str()) {
+                rooms.push(&event.room);
+            }
+        }
+        // Stable: the events of a room stay sorted by time
+        events.sort_by_key(|event| rooms.iter().position(|room| *room == event.room));
+    }
+    events
+}
+
+fn layout_events(envelope: &Envelope, style: &Style, width: i32) -> Vec<EventRows> {
+    ordered_events(envelope)
+        .into_iter()
+        .map(|event| {
+            let datetime = format!(" {} ", text::transliterate(&event.datetime));
+            // The title is padded with a space on both sides as well
+            let title_chars = style.chars_in(width).saturating_sub(datetime.chars().count() + 2);
+            EventRows {
+                room: match envelope.topic_kind {
+                    TopicKind::Building => Some(text::transliterate(envelope.room_name(&event.room))),
+                    TopicKind::Room => None,
+                },
+                title: text::wrap(&text::transliterate(&event.title), title_chars, style.title_lines),
+                datetime,
+            }
+        })
+        .collect()
+}
+
+/// Splits the events in pages, without splitting the rows of an event across pages. A group of events continuing
+/// on the next page repeats its room header there.
+fn paginate(events: &[EventRows], rows: usize) -> Vec<Range<usize>> {
+    let mut pages = Vec::new();
+    let mut start = 0;
+    let mut used = 0;
+    for (i, event) in events.iter().enumerate() {
+        let previous = if i > start { Some(&events[i - 1]) } else { None };
+        let mut height = event.height(previous);
+        if used > 0 && used + height > rows {
+            pages.push(start..i);
+            start = i;
+            used = 0;
+            height = event.height(None);
+        }
+        used += height;
+    }
+    pages.push(start..events.len());
+    pages
+}
+
+/// How many events fit on a page of a display of the given size: the header takes the first row(s), and the last
+/// row is kept for the footer (the "and N more" line and the page indicator).
+pub fn rows_per_page(style: &Style, size: Size) -> usize {
+    let rows = (style.footer_y(size.height as i32) - style.header_height) / style.row_height();
+    rows.max(1) as usize
+}
+
+pub fn page_count(envelope: &Envelope, style: &Style, size: Size) -> usize {
+    let events = layout_events(envelope, style, size.width as i32);
+    paginate(&events, rows_per_page(style, size)).len()
+}
+
+/// Lays out a page of the payload on any 1-bit display: the header with the room and building names, then one row
+/// per event, with the title on the left and the date on the right. Titles too long for their row wrap on the
+/// following rows, up to [`Style::title_lines`], and are cut with an ellipsis past that.
+/// The layout follows the topic of the payload: a building screen groups the events under a header per room.
+/// Pages past the last one are rendered as the last page. Without events, the screen is [`render_idle`].
+pub fn render<D>(envelope: &Envelope, style: &Style, page: usize, display: &mut D) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    if is_idle(envelope) {
+        return render_idle(envelope, None, style, display);
+    }
+    display.clear(BinaryColor::Off)?;
+    let size = display.bounding_box().size;
+    let width = size.width as i32;
+    let events = layout_events(envelope, style, width);
+    let pages = paginate(&events, rows_per_page(style, size));
+    let page = page.min(pages.len() - 1);
+
+    let header = text::transliterate(&place_name(envelope));
+    let header = format!(" {} ", text::truncate(&header, style.chars_in(width).saturating_sub(2)));
+    draw_text(display, style, &header, width / 2, 0, Alignment::Center)?;
+    let mut y = style.header_height;
+    let range = pages[page].clone();
+    for (i, event) in events.iter().enumerate().take(range.end).skip(range.start) {
+        let previous = if i > range.start { Some(&events[i - 1]) } else { None };
+        if event.opens_group(previous) {
+            draw_room_header(display, style, event.room.as_deref().unwrap_or_default(), width, y)?;
+            y += style.row_height();
+        }
+        draw_text(display, style, &event.datetime, width, y, Alignment::Right)?;
+        for line in &event.title {
+            draw_text(display, style, &format!(" {} ", line), 0, y, Alignment::Left)?;
+            y += style.row_height();
+        }
+        if event.title.is_empty() {
+            y += style.row_height();
+        }
+    }
+
+    let footer_y = style.footer_y(size.height as i32);
+    let pages = pages.len();
+    if envelope.more_events > 0 && page == pages - 1 {
+        let more = format!(" ... e altri {} ", envelope.more_events);
+        draw_text(display, style, &more, 0, footer_y, Alignment::Left)?;
+    }
+    if pages > 1 {
+        let indicator = format!(" pagina {}/{} ", page + 1, pages);
+        draw_text(display, style, &indicator, width, footer_y, Alignment::Right)?;
+    }
+    Ok(())
+}
+
+/// The room and building names of the header
+fn place_name(envelope: &Envelope) -> String {
+    match &envelope.room {
+        Some(room) => format!("{} ({})", room.name, envelope.building.name),
+        None => envelope.building.name.clone(),
+    }
+}
+
+/// Whether the payload has no events to show, not even left out ones: see [`render_idle`]
+pub fn is_idle(envelope: &Envelope) -> bool {
+    envelope.events.is_empty() && envelope.more_events == 0
+}
+
+/// The screen of a place without events, so that it doesn't look like a broken display: the room (or building) name
+/// in the middle of the screen, "Nessun evento in programma", and when the schedule has been received if known
+/// (`synced_at`, eg. `10:42`).
+pub fn render_idle<D>(
+    envelope: &Envelope,
+    synced_at: Option<&str>,
+    style: &Style,
+    display: &mut D,
+) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let mut details = vec!["Nessun evento in programma".to_string()];
+    if let Some(synced_at) = synced_at {
+        details.push(format!("Aggiornato alle {}", synced_at));
+    }
+    render_notice(&place_name(envelope), &details, style, display)
+}
+
+/// A full screen notice, for when there's no schedule to show (eg. the network can't be reached): the title in the
+/// middle of the screen, and the details below it.
+pub fn render_notice<D>(title: &str, details: &[String], style: &Style, display: &mut D) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    display.clear(BinaryColor::Off)?;
+    let size = display.bounding_box().size;
+    let width = size.width as i32;
+    let max_chars = style.chars_in(width).saturating_sub(2);
+    let lines: Vec<String> = details
+        .iter()
+        .flat_map(|detail| text::wrap(&text::transliterate(detail), max_chars, 2))
+        .collect();
+    // The title and an empty row, then the details
+    let height = (lines.len() as i32 + 2) * style.row_height();
+    let mut y = (size.height as i32 - height).max(0) / 2;
+    let title = format!(" {} ", text::truncate(&text::transliterate(title), max_chars));
+    draw_text(display, style, &title, width / 2, y, Alignment::Center)?;
+    y += 2 * style.row_height();
+    for line in lines {
+        draw_text(display, style, &format!(" {} ", line), width / 2, y, Alignment::Center)?;
+        y += style.row_height();
+    }
+    Ok(())
+}
+
+/// Draws a warning in white on black over the footer row of a screen already rendered, eg. to tell that the schedule
+/// shown might be outdated.
+pub fn draw_banner<D>(text: &str, style: &Style, display: &mut D) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let size = display.bounding_box().size;
+    let y = size.height as i32 - style.row_height();
+    Rectangle::new(Point::new(0, y), Size::new(size.width, style.row_height() as u32))
+        .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+        .draw(display)?;
+    let text = text::truncate(&text::transliterate(text), style.chars_in(size.width as i32));
+    let character_style = MonoTextStyleBuilder::new()
+        .font(style.font)
+        .text_color(BinaryColor::Off)
+        .build();
+    let text_style = TextStyleBuilder::new()
+        .baseline(Baseline::Top)
+        .alignment(Alignment::Center)
+        .build();
+    Text::with_text_style(&text, Point::new(size.width as i32 / 2, y), character_style, text_style).draw(display)?;
+    Ok(())
+}
+
+/// The status of the display, shown on the last row of the screen: see [`Style::status_bar`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StatusBar {
+    /// On the left, eg. the time of the last update
+    pub left: String,
+    /// On the right, eg. the Wi-Fi signal and the battery level
+    pub right: String,
+    /// Draws the bar inverted (white on black), eg. when the schedule is stale
+    pub warning: bool,
+}
+
+/// Draws the status bar on the last row, under a separator line, or inverted for a warning
+pub fn draw_status_bar<D>(status: &StatusBar, style: &Style, display: &mut D) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let size = display.bounding_box().size;
+    let width = size.width as i32;
+    let y = size.height as i32 - style.row_height();
+    let (background, foreground) = match status.warning {
+        true => (BinaryColor::On, BinaryColor::Off),
+        false => (BinaryColor::Off, BinaryColor::On),
+    };
+    Rectangle::new(Point::new(0, y), Size::new(size.width, style.row_height() as u32))
+        .into_styled(PrimitiveStyle::with_fill(background))
+        .draw(display)?;
+    if !status.warning {
+        Rectangle::new(Point::new(0, y), Size::new(size.width, 1))
+            .into_styled(PrimitiveStyle::with_fill(foreground))
+            .draw(display)?;
+    }
+    // The right side is the shortest, it's never cut
+    let right = text::truncate(
+        &text::transliterate(&status.right),
+        style.chars_in(width).saturating_sub(2),
+    );
+    let left_chars = style.chars_in(width).saturating_sub(right.chars().count() + 3);
+    let left = text::truncate(&text::transliterate(&status.left), left_chars);
+    let character_style = MonoTextStyleBuilder::new()
+        .font(style.font)
+        .text_color(foreground)
+        .build();
+    for (text, x, alignment) in [
+        (format!(" {}", left), 0, Alignment::Left),
+        (format!("{} ", right), width, Alignment::Right),
+    ] {
+        let text_style = TextStyleBuilder::new()
+            .baseline(Baseline::Top)
+            .alignment(alignment)
+            .build();
+        Text::with_text_style(&text, Point::new(x, y), character_style, text_style).draw(display)?;
+    }
+    Ok(())
+}
+
+/// A small inverted `!` in the top right corner of the header, over a schedule that's being kept on screen because
+/// its update couldn't be shown (eg. a malformed payload).
+pub fn draw_error_glyph<D>(style: &Style, display: &mut D) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let width = display.bounding_box().size.width as i32;
+    let side = style.row_height();
+    Rectangle::new(Point::new(width - side, 0), Size::new(side as u32, side as u32))
+        .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+        .draw(display)?;
+    let character_style = MonoTextStyleBuilder::new()
+        .font(style.font)
+        .text_color(BinaryColor::Off)
+        .build();
+    let text_style = TextStyleBuilder::new()
+        .baseline(Baseline::Top)
+        .alignment(Alignment::Center)
+        .build();
+    Text::with_text_style("!", Point::new(width - side / 2, 0), character_style, text_style).draw(display)?;
+    Ok(())
+}
+
+/// Renders every page of the payload for a display model
+pub fn render_pages(envelope: &Envelope, model: DisplayModel) -> Vec<Framebuffer> {
+    let style = Style::for_model(model);
+    let pages = page_count(envelope, &style, screen_size(model));
+    (0..pages)
+        .map(|page| {
+            let mut framebuffer = Framebuffer::new(model);
+            // Drawing on a framebuffer can't fail
+            render(envelope, &style, page, &mut framebuffer).unwrap();
+            framebuffer
+        })
+        .collect()
+}
+
+/// The name of a room on the left, underlined across the whole screen
+fn draw_room_header<D>(display: &mut D, style: &Style, room: &str, width: i32, y: i32) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let room = text::truncate(room, style.chars_in(width).saturating_sub(2));
+    draw_text(display, style, &format!(" {} ", room), 0, y, Alignment::Left)?;
+    Rectangle::new(Point::new(0, y + style.row_height() - 1), Size::new(width as u32, 1))
+        .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+        .draw(display)?;
+    Ok(())
+}
+
+fn draw_text<D>(display: &mut D, style: &Style, text: &str, x: i32, y: i32, align: Alignment) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let character_style = MonoTextStyleBuilder::new()
+        .font(style.font)
+        .text_color(BinaryColor::On)
+        .background_color(BinaryColor::Off)
+        .build();
+
+    let text_style = TextStyleBuilder::new().baseline(Baseline::Top).alignment(align).build();
+
+    Text::with_text_style(text, Point::new(x, y), character_style, text_style).draw(display)?;
+    Ok(())
+}
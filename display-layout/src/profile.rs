@@ -0,0 +1,119 @@
+//! What the layout needs to know about each e-paper panel. The firmware extends these profiles with the driver of the
+//! panel, the lambda only needs the layout.
+
+use display_payload::DisplayModel;
+use embedded_graphics::{
+    mono_font::iso_8859_1::{FONT_10X20, FONT_7X13_BOLD, FONT_9X18_BOLD},
+    prelude::*,
+};
+
+use crate::Style;
+
+pub trait Profile {
+    /// The panel, which decides the resolution of the native buffer (see [`DisplayModel::native_size`])
+    fn model(&self) -> DisplayModel;
+
+    /// Whether the panel is mounted in landscape, rotated by 90 degrees compared to its native orientation
+    fn rotated(&self) -> bool {
+        false
+    }
+
+    /// Fonts and spacing of the layout
+    fn style(&self) -> Style;
+
+    /// Size of the screen as seen by a person (already rotated)
+    fn size(&self) -> Size {
+        let (width, height) = self.model().native_size();
+        if self.rotated() {
+            Size::new(height, width)
+        } else {
+            Size::new(width, height)
+        }
+    }
+}
+
+/// Waveshare 2.9" (V2): a single line per title, the screen only has 7 rows for the events
+pub struct Epd2in9V2;
+
+impl Profile for Epd2in9V2 {
+    fn model(&self) -> DisplayModel {
+        DisplayModel::Epd2in9V2
+    }
+
+    fn rotated(&self) -> bool {
+        true
+    }
+
+    fn style(&self) -> Style {
+        Style {
+            font: &FONT_7X13_BOLD,
+            header_height: 20,
+            title_lines: 1,
+            status_bar: false,
+        }
+    }
+}
+
+/// Waveshare 4.2"
+pub struct Epd4in2;
+
+impl Profile for Epd4in2 {
+    fn model(&self) -> DisplayModel {
+        DisplayModel::Epd4in2
+    }
+
+    fn style(&self) -> Style {
+        Style {
+            font: &FONT_9X18_BOLD,
+            header_height: 20,
+            title_lines: 2,
+            status_bar: false,
+        }
+    }
+}
+
+/// Waveshare 5.83" (V2)
+pub struct Epd5in83V2;
+
+impl Profile for Epd5in83V2 {
+    fn model(&self) -> DisplayModel {
+        DisplayModel::Epd5in83V2
+    }
+
+    fn style(&self) -> Style {
+        Style {
+            font: &FONT_10X20,
+            header_height: 20,
+            title_lines: 2,
+            status_bar: false,
+        }
+    }
+}
+
+/// Waveshare 7.5" (V2)
+pub struct Epd7in5V2;
+
+impl Profile for Epd7in5V2 {
+    fn model(&self) -> DisplayModel {
+        DisplayModel::Epd7in5V2
+    }
+
+    fn style(&self) -> Style {
+        Style {
+            font: &FONT_10X20,
+            header_height: 24,
+            title_lines: 2,
+            status_bar: false,
+        }
+    }
+}
+
+/// The profile of a display model
+pub fn profile(model: DisplayModel) -> &'static dyn Profile {
+    match model {
+        DisplayModel::Epd2in9V2 => &Epd2in9V2,
+        DisplayModel::Epd4in2 => &Epd4in2,
+        DisplayModel::Epd5in83V2 => &Epd5in83V2,
+        DisplayModel::Epd7in5V2 => &Epd7in5V2,
+    }
+}
@@ -0,0 +1,96 @@
+/// Appended to text cut because it doesn't fit
+pub const ELLIPSIS: &str = "...";
+
+/// Rewrites `text` with the characters of the Latin-1 fonts only: typographic punctuation and the letters of the
+/// other Latin alphabets get their closest Latin-1 equivalent, while emoji and other symbols are dropped, rather
+/// than being drawn as question marks. Runs of spaces left behind are collapsed.
+pub fn transliterate(text: &str) -> String {
+    let mut transliterated = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\u{20}'..='\u{7E}' | '\u{A0}'..='\u{FF}' => transliterated.push(c),
+            // Control characters (eg. new lines in a title) would break the layout
+            '\u{0}'..='\u{1F}' | '\u{7F}'..='\u{9F}' => transliterated.push(' '),
+            '‘' | '’' | '‚' | '′' => transliterated.push('\''),
+            '“' | '”' | '„' | '″' => transliterated.push('"'),
+            '‐' | '‑' | '‒' | '–' | '—' | '−' => transliterated.push('-'),
+            '…' => transliterated.push_str("..."),
+            '•' => transliterated.push('·'),
+            '€' => transliterated.push_str("EUR"),
+            '\u{2000}'..='\u{200A}' | '\u{202F}' => transliterated.push(' '),
+            'Œ' => transliterated.push_str("OE"),
+            'œ' => transliterated.push_str("oe"),
+            'Ā' | 'Ă' | 'Ą' => transliterated.push('A'),
+            'ā' | 'ă' | 'ą' => transliterated.push('a'),
+            'Ć' | 'Ĉ' | 'Ċ' | 'Č' => transliterated.push('C'),
+            'ć' | 'ĉ' | 'ċ' | 'č' => transliterated.push('c'),
+            'Ď' | 'Đ' => transliterated.push('D'),
+            'ď' | 'đ' => transliterated.push('d'),
+            'Ē' | 'Ĕ' | 'Ė' | 'Ę' | 'Ě' => transliterated.push('E'),
+            'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => transliterated.push('e'),
+            'Ğ' | 'Ġ' | 'Ģ' => transliterated.push('G'),
+            'ğ' | 'ġ' | 'ģ' => transliterated.push('g'),
+            'Ī' | 'Ĭ' | 'Į' | 'İ' => transliterated.push('I'),
+            'ī' | 'ĭ' | 'į' | 'ı' => transliterated.push('i'),
+            'Ł' | 'Ľ' | 'Ĺ' => transliterated.push('L'),
+            'ł' | 'ľ' | 'ĺ' => transliterated.push('l'),
+            'Ń' | 'Ň' | 'Ņ' => transliterated.push('N'),
+            'ń' | 'ň' | 'ņ' => transliterated.push('n'),
+            'Ō' | 'Ŏ' | 'Ő' => transliterated.push('O'),
+            'ō' | 'ŏ' | 'ő' => transliterated.push('o'),
+            'Ŕ' | 'Ř' => transliterated.push('R'),
+            'ŕ' | 'ř' => transliterated.push('r'),
+            'Ś' | 'Ş' | 'Š' | 'Ș' => transliterated.push('S'),
+            'ś' | 'ş' | 'š' | 'ș' => transliterated.push('s'),
+            'Ţ' | 'Ť' | 'Ț' => transliterated.push('T'),
+            'ţ' | 'ť' | 'ț' => transliterated.push('t'),
+            'Ū' | 'Ŭ' | 'Ů' | 'Ű' | 'Ų' => transliterated.push('U'),
+            'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => transliterated.push('u'),
+            'Ÿ' => transliterated.push('Y'),
+            'Ź' | 'Ż' | 'Ž' => transliterated.push('Z'),
+            'ź' | 'ż' | 'ž' => transliterated.push('z'),
+            // Any other letter or digit is worth a placeholder, so that the title keeps its shape
+            c if c.is_alphanumeric() => transliterated.push('?'),
+            // Emoji, their modifiers and joiners, and any other symbol
+            _ => {}
+        }
+    }
+    transliterated.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Splits `text` into at most `max_lines` lines of at most `max_chars` characters (the fonts are monospaced),
+/// breaking between words when possible. If the text still doesn't fit, the last line ends with an ellipsis.
+pub fn wrap(text: &str, max_chars: usize, max_lines: usize) -> Vec<String> {
+    let max_lines = max_lines.max(1);
+    let mut lines = Vec::new();
+    let mut rest = text.trim();
+    while !rest.is_empty() {
+        if lines.len() + 1 == max_lines || rest.chars().count() <= max_chars {
+            lines.push(truncate(rest, max_chars));
+            break;
+        }
+        // The first character that doesn't fit: if it's a space, it can still be used to break the line
+        let (limit, overflow) = rest.char_indices().nth(max_chars).unwrap();
+        let (line, next) = match rest[..limit + overflow.len_utf8()].rfind(char::is_whitespace) {
+            Some(space) if space > 0 => rest.split_at(space),
+            // A single word longer than the line: cut it where it overflows (keeping at least a character per line)
+            _ => rest.split_at(limit.max(rest.chars().next().unwrap().len_utf8())),
+        };
+        lines.push(line.trim_end().to_string());
+        rest = next.trim_start();
+    }
+    lines
+}
+
+/// Cuts `text` to `max_chars` characters, ending it with an ellipsis if anything has been left out and it fits
+pub fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    // No room for the ellipsis
+    if max_chars < ELLIPSIS.len() {
+        return text.chars().take(max_chars).collect();
+    }
+    let kept: String = text.chars().take(max_chars.saturating_sub(ELLIPSIS.len())).collect();
+    format!("{}{}", kept.trim_end(), ELLIPSIS)
+}
@@ -0,0 +1,378 @@
+//! Golden-image tests of the layout on the supported panels.
+//! Run with `UPDATE_GOLDEN=1` to regenerate the images in tests/golden after an intended layout change, and check
+//! them with any image viewer (they're plain PBM files).
+
+use std::{env, fs, path::PathBuf};
+
+use display_layout::{
+    draw_banner, draw_error_glyph, draw_status_bar, is_idle, page_count, render, render_idle, render_notice,
+    render_pages, screen_size, Framebuffer, StatusBar, Style,
+};
+use display_payload::{DisplayModel, Envelope, Place, SEvent, TopicKind};
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+
+const MODELS: [DisplayModel; 4] = DisplayModel::ALL;
+
+fn event(title: &str, datetime: &str, room: &str) -> SEvent {
+    SEvent {
+        id: title.to_string(),
+        title: title.to_string(),
+        timestamp: 1688724000,
+        datetime: datetime.to_string(),
+        building: "F3".to_string(),
+        room: room.to_string(),
+    }
+}
+
+fn room_envelope() -> Envelope {
+    let mut envelope = Envelope::new(
+        1688720000,
+        TopicKind::Room,
+        Place {
+            id: "F3".to_string(),
+            name: "Edificio F3".to_string(),
+        },
+        Some(Place {
+            id: "P6".to_string(),
+            name: "Aula P6".to_string(),
+        }),
+        vec![
+            event("Riunione di dipartimento", "2023-07-07 10:00", "P6"),
+            event("Seminario IoT", "2023-07-07 14:30", "P6"),
+            event("Esame di Reti", "2023-07-08 09:00", "P6"),
+        ],
+    );
+    envelope.more_events = 2;
+    envelope
+}
+
+fn building_envelope() -> Envelope {
+    Envelope::new(
+        1688720000,
+        TopicKind::Building,
+        Place {
+            id: "F3".to_string(),
+            name: "Edificio F3".to_string(),
+        },
+        None,
+        vec![
+            event("Riunione di dipartimento", "2023-07-07 10:00", "P6"),
+            event("Laboratorio", "2023-07-07 11:00", "P3"),
+        ],
+    )
+}
+
+/// Encodes the screen as seen by a person (already rotated) as a binary PBM image
+fn to_pbm(framebuffer: &Framebuffer) -> Vec<u8> {
+    let size = framebuffer.size();
+    let mut pbm = format!("P4\n{} {}\n", size.width, size.height).into_bytes();
+    for y in 0..size.height as i32 {
+        for chunk_x in (0..size.width as i32).step_by(8) {
+            let mut byte = 0u8;
+            for x in chunk_x..(chunk_x + 8).min(size.width as i32) {
+                if framebuffer.pixel(Point::new(x, y)) == Some(BinaryColor::On) {
+                    byte |= 0x80 >> (x - chunk_x);
+                }
+            }
+            pbm.push(byte);
+        }
+    }
+    pbm
+}
+
+fn assert_snapshot(name: &str, framebuffer: &Framebuffer) {
+    let golden_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.pbm", name));
+    let actual = to_pbm(framebuffer);
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&golden_path, &actual).unwrap();
+        return;
+    }
+    let golden = fs::read(&golden_path).unwrap_or_else(|_| {
+        panic!(
+            "Missing {}, run with UPDATE_GOLDEN=1 to create it",
+            golden_path.display()
+        )
+    });
+    if golden != actual {
+        let actual_path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.actual.pbm", name));
+        fs::write(&actual_path, &actual).unwrap();
+        panic!("{} doesn't match {}", actual_path.display(), golden_path.display());
+    }
+}
+
+/// Checks every page of the payload on every panel
+fn render_snapshot(name: &str, envelope: &Envelope) {
+    for model in MODELS {
+        for (page, framebuffer) in render_pages(envelope, model).iter().enumerate() {
+            let name = match page {
+                0 => format!("{}_{}", name, model.name()),
+                _ => format!("{}_{}_page{}", name, model.name(), page + 1),
+            };
+            assert_snapshot(&name, framebuffer);
+        }
+    }
+}
+
+#[test]
+fn room_schedule() {
+    render_snapshot("room", &room_envelope());
+}
+
+#[test]
+fn building_schedule() {
+    render_snapshot("building", &building_envelope());
+}
+
+#[test]
+fn building_grouped_by_room() {
+    let mut envelope = building_envelope();
+    envelope.events = vec![
+        event("Riunione di dipartimento", "2023-07-07 10:00", "P6"),
+        event("Laboratorio", "2023-07-07 11:00", "P3"),
+        event("Seminario IoT", "2023-07-07 14:30", "P6"),
+        event("Esame di Reti", "2023-07-08 09:00", "P1"),
+        event("Ricevimento", "2023-07-08 11:00", "P3"),
+        event("Esame di Sistemi Embedded", "2023-07-09 09:00", "P6"),
+    ];
+    // P1 has no name: shown by its id
+    envelope.room_names = vec![
+        Place {
+            id: "P6".to_string(),
+            name: "Aula P6".to_string(),
+        },
+        Place {
+            id: "P3".to_string(),
+            name: "Laboratorio P3".to_string(),
+        },
+    ];
+    render_snapshot("building_rooms", &envelope);
+}
+
+#[test]
+fn room_headers_take_a_row() {
+    // 7 rows between the header and the footer on the 2.9" panel: a room header and 6 events fit, 7 don't
+    let model = DisplayModel::Epd2in9V2;
+    let style = Style::for_model(model);
+    let mut envelope = building_envelope();
+    envelope.events = (0..6).map(|i| event(&i.to_string(), "", "P6")).collect();
+    assert_eq!(page_count(&envelope, &style, screen_size(model)), 1);
+    envelope.events.push(event("6", "", "P6"));
+    assert_eq!(page_count(&envelope, &style, screen_size(model)), 2);
+    // The same events on a room screen have no header
+    envelope.topic_kind = TopicKind::Room;
+    assert_eq!(page_count(&envelope, &style, screen_size(model)), 1);
+    // A second room takes another header row
+    envelope.topic_kind = TopicKind::Building;
+    envelope.events.truncate(5);
+    envelope.events.push(event("5", "", "P3"));
+    assert_eq!(page_count(&envelope, &style, screen_size(model)), 2);
+}
+
+#[test]
+fn paginated_schedule() {
+    let mut envelope = room_envelope();
+    envelope.events = (0..25)
+        .map(|i| {
+            event(
+                &format!("Evento {}", i + 1),
+                &format!("2023-07-{:02} 10:00", i + 7),
+                "P6",
+            )
+        })
+        .collect();
+    envelope.more_events = 3;
+    render_snapshot("paginated", &envelope);
+}
+
+#[test]
+fn long_titles() {
+    let mut envelope = room_envelope();
+    envelope.room.as_mut().unwrap().name = "Aula magna del dipartimento di ingegneria".to_string();
+    envelope.events = vec![
+        event(
+            "Presentazione dei progetti del corso di Sistemi Embedded e Internet of Things",
+            "2023-07-07 10:00",
+            "P6",
+        ),
+        event("Seminario", "2023-07-07 14:30", "P6"),
+        event(
+            "Precipitevolissimevolmente_senza_spazi_da_nessuna_parte",
+            "2023-07-08 09:00",
+            "P6",
+        ),
+    ];
+    render_snapshot("long_titles", &envelope);
+}
+
+#[test]
+fn accented_titles() {
+    let mut envelope = room_envelope();
+    envelope.events = vec![
+        event("Riunione dell'università", "2023-07-07 10:00", "P6"),
+        event("Attività di orientamento 🎓", "2023-07-07 14:30", "P6"),
+        event("Perché “è” così…", "2023-07-08 09:00", "P6"),
+    ];
+    render_snapshot("accented", &envelope);
+}
+
+#[test]
+fn wrapped_titles_stay_on_one_page() {
+    // 22 rows between the header and the footer on the 5.83" panel: 11 titles taking two rows each fit, 12 don't
+    let title = "Evento con un titolo molto lungo che va a capo sulla riga successiva";
+    let mut envelope = room_envelope();
+    envelope.events = (0..11).map(|_| event(title, "2023-07-07 10:00", "P6")).collect();
+    let model = DisplayModel::Epd5in83V2;
+    let style = Style::for_model(model);
+    assert_eq!(page_count(&envelope, &style, screen_size(model)), 1);
+    envelope.events.push(event(title, "2023-07-07 10:00", "P6"));
+    assert_eq!(page_count(&envelope, &style, screen_size(model)), 2);
+}
+
+#[test]
+fn idle_screen() {
+    let mut envelope = room_envelope();
+    envelope.events.clear();
+    assert!(!is_idle(&envelope), "the events left out are still shown");
+    envelope.more_events = 0;
+    assert!(is_idle(&envelope));
+    for model in MODELS {
+        let mut framebuffer = Framebuffer::new(model);
+        render_idle(&envelope, Some("10:42"), &Style::for_model(model), &mut framebuffer).unwrap();
+        assert_snapshot(&format!("idle_{}", model.name()), &framebuffer);
+    }
+    // Without the time of the update, eg. on the frames rendered by the server
+    render_snapshot("idle_unsynced", &envelope);
+}
+
+#[test]
+fn notice() {
+    for model in MODELS {
+        let mut framebuffer = Framebuffer::new(model);
+        let details = [
+            "Reti provate: Aula Magna, Wokwi-GUEST".to_string(),
+            "Nuovo tentativo tra 10 min".to_string(),
+        ];
+        render_notice(
+            "Rete non raggiungibile",
+            &details,
+            &Style::for_model(model),
+            &mut framebuffer,
+        )
+        .unwrap();
+        assert_snapshot(&format!("notice_{}", model.name()), &framebuffer);
+    }
+}
+
+#[test]
+fn banner_over_schedule() {
+    for model in MODELS {
+        let mut framebuffer = render_pages(&room_envelope(), model).remove(0);
+        draw_banner("Rete non raggiungibile", &Style::for_model(model), &mut framebuffer).unwrap();
+        assert_snapshot(&format!("banner_{}", model.name()), &framebuffer);
+    }
+}
+
+#[test]
+fn error_glyph_over_schedule() {
+    for model in MODELS {
+        let mut framebuffer = render_pages(&room_envelope(), model).remove(0);
+        draw_error_glyph(&Style::for_model(model), &mut framebuffer).unwrap();
+        assert_snapshot(&format!("error_glyph_{}", model.name()), &framebuffer);
+    }
+}
+
+#[test]
+fn status_bar() {
+    // room_envelope has more events than shown: the footer moves above the status bar
+    for (warning, name) in [(false, "status"), (true, "status_stale")] {
+        for model in MODELS {
+            let mut style = Style::for_model(model);
+            style.status_bar = true;
+            let mut framebuffer = Framebuffer::new(model);
+            render(&room_envelope(), &style, 0, &mut framebuffer).unwrap();
+            let status = StatusBar {
+                left: "Aggiornato 10:42".to_string(),
+                right: "-67 dBm 80%".to_string(),
+                warning,
+            };
+            draw_status_bar(&status, &style, &mut framebuffer).unwrap();
+            assert_snapshot(&format!("{}_{}", name, model.name()), &framebuffer);
+        }
+    }
+}
+
+#[test]
+fn status_bar_changes_its_rows_only() {
+    for model in MODELS {
+        let mut style = Style::for_model(model);
+        style.status_bar = true;
+        let mut status = StatusBar {
+            left: "Aggiornato 10:42".to_string(),
+            right: "-67 dBm 80%".to_string(),
+            warning: false,
+        };
+        let mut before = Framebuffer::new(model);
+        render(&room_envelope(), &style, 0, &mut before).unwrap();
+        let mut after = Framebuffer::new(model);
+        render(&room_envelope(), &style, 0, &mut after).unwrap();
+        draw_status_bar(&status, &style, &mut before).unwrap();
+        status.left = "Aggiornato 10:57".to_string();
+        draw_status_bar(&status, &style, &mut after).unwrap();
+        let (before, after) = (before.row_hashes(), after.row_hashes());
+        assert_eq!(before.len(), screen_size(model).height as usize);
+        let changed: Vec<usize> = (0..before.len()).filter(|&y| before[y] != after[y]).collect();
+        let status_bar_y = before.len() - style.font.character_size.height as usize;
+        assert!(!changed.is_empty());
+        assert!(changed.iter().all(|&y| y >= status_bar_y), "{:?}", changed);
+    }
+}
+
+#[test]
+fn status_bar_takes_a_row() {
+    let mut envelope = room_envelope();
+    envelope.events = (0..7).map(|i| event(&i.to_string(), "", "P6")).collect();
+    let mut style = Style::for_model(DisplayModel::Epd2in9V2);
+    assert_eq!(page_count(&envelope, &style, screen_size(DisplayModel::Epd2in9V2)), 1);
+    style.status_bar = true;
+    assert_eq!(page_count(&envelope, &style, screen_size(DisplayModel::Epd2in9V2)), 2);
+}
+
+#[test]
+fn pages_fit_the_panel() {
+    let envelope = room_envelope();
+    for model in MODELS {
+        assert_eq!(page_count(&envelope, &Style::for_model(model), screen_size(model)), 1);
+    }
+    let mut envelope = room_envelope();
+    envelope.events = (0..8).map(|i| event(&i.to_string(), "", "P6")).collect();
+    let style = Style::for_model(DisplayModel::Epd2in9V2);
+    // 7 rows between the header and the footer
+    assert_eq!(page_count(&envelope, &style, screen_size(DisplayModel::Epd2in9V2)), 2);
+    envelope.events.pop();
+    assert_eq!(page_count(&envelope, &style, screen_size(DisplayModel::Epd2in9V2)), 1);
+}
+
+#[test]
+fn framebuffer_layout_matches_driver() {
+    // The 2.9" panel is rotated: the top left corner of the screen is the top right corner of the native buffer
+    let mut framebuffer = Framebuffer::new(DisplayModel::Epd2in9V2);
+    assert_eq!(framebuffer.size(), Size::new(296, 128));
+    Pixel(Point::new(0, 0), BinaryColor::On).draw(&mut framebuffer).unwrap();
+    assert_eq!(framebuffer.buffer()[15], 0b1111_1110);
+    assert!(framebuffer
+        .buffer()
+        .iter()
+        .enumerate()
+        .all(|(i, byte)| i == 15 || *byte == 0xFF));
+
+    let mut framebuffer = Framebuffer::new(DisplayModel::Epd5in83V2);
+    assert_eq!(framebuffer.size(), Size::new(648, 480));
+    Pixel(Point::new(9, 1), BinaryColor::On).draw(&mut framebuffer).unwrap();
+    assert_eq!(framebuffer.buffer()[81 + 1], 0b1011_1111);
+
+    // The 4.2" and 7.5" panels aren't rotated either
+    assert_eq!(screen_size(DisplayModel::Epd4in2), Size::new(400, 300));
+    assert_eq!(screen_size(DisplayModel::Epd7in5V2), Size::new(800, 480));
+}
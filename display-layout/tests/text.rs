@@ -0,0 +1,61 @@
+use display_layout::text::{transliterate, truncate, wrap};
+
+#[test]
+fn short_text_untouched() {
+    assert_eq!(wrap("Seminario IoT", 20, 2), vec!["Seminario IoT"]);
+    assert_eq!(truncate("Seminario IoT", 13), "Seminario IoT");
+}
+
+#[test]
+fn wraps_between_words() {
+    assert_eq!(
+        wrap("Riunione di dipartimento", 12, 3),
+        vec!["Riunione di", "dipartimento"]
+    );
+    // A space right after the last character that fits is a fine place to break
+    assert_eq!(wrap("Riunione di", 8, 2), vec!["Riunione", "di"]);
+}
+
+#[test]
+fn truncates_last_line() {
+    assert_eq!(
+        wrap("Riunione di dipartimento straordinaria", 12, 2),
+        vec!["Riunione di", "dipartime..."]
+    );
+    assert_eq!(wrap("Riunione di dipartimento", 12, 1), vec!["Riunione..."]);
+}
+
+#[test]
+fn truncates_within_narrow_widths() {
+    assert_eq!(truncate("Riunione", 3), "...");
+    assert_eq!(truncate("Riunione", 2), "Ri");
+    assert_eq!(truncate("Riunione", 1), "R");
+    assert_eq!(truncate("Riunione", 0), "");
+}
+
+#[test]
+fn splits_long_words() {
+    assert_eq!(
+        wrap("Precipitevolissimevolmente", 10, 3),
+        vec!["Precipitev", "olissimevo", "lmente"]
+    );
+}
+
+#[test]
+fn counts_characters_not_bytes() {
+    assert_eq!(wrap("Attività università", 9, 2), vec!["Attività", "univer..."]);
+}
+
+#[test]
+fn keeps_latin1() {
+    assert_eq!(transliterate("Riunione dell'università"), "Riunione dell'università");
+    assert_eq!(transliterate("Perché è così? ÀÉÌÒÙ"), "Perché è così? ÀÉÌÒÙ");
+}
+
+#[test]
+fn transliterates_the_rest() {
+    assert_eq!(transliterate("L’aula “magna” – 10€…"), "L'aula \"magna\" - 10EUR...");
+    assert_eq!(transliterate("Łódź, Škoda"), "Lódz, Skoda");
+    assert_eq!(transliterate("Festa 🎉 di fine anno 👩‍💻"), "Festa di fine anno");
+    assert_eq!(transliterate("Seminario\nIoT"), "Seminario IoT");
+}
@@ -0,0 +1,76 @@
+//! What's left of the schedule between two payloads: events that are over are hidden, and the upcoming ones get a
+//! label relative to the current time ("in corso", "tra 20 min") in place of their date.
+
+use std::time::Duration;
+
+use display_payload::{Envelope, SEvent};
+
+/// How the schedule is shown as time goes by
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Agenda {
+    /// How long an event is shown as ongoing after its start: the payload doesn't tell when events end
+    pub ongoing_for: Duration,
+    /// How long before its start an event gets a countdown instead of its date
+    pub countdown_from: Duration,
+    /// Granularity of the countdown, rounded up: every step is a refresh of the panel
+    pub countdown_step: Duration,
+}
+
+impl Agenda {
+    /// The payload as it should be shown at `now`
+    pub fn at(&self, envelope: &Envelope, now: u64) -> Envelope {
+        let mut envelope = envelope.clone();
+        envelope.events = envelope
+            .events
+            .into_iter()
+            .filter_map(|event| {
+                let datetime = self.label(&event, now)?;
+                Some(SEvent { datetime, ..event })
+            })
+            .collect();
+        envelope
+    }
+
+    /// The first moment after `now` when [`Agenda::at`] changes: an event ends, starts or its countdown ticks
+    pub fn next_change(&self, envelope: &Envelope, now: u64) -> Option<u64> {
+        envelope
+            .events
+            .iter()
+            .filter_map(|event| self.event_change(event, now))
+            .min()
+    }
+
+    /// What's shown in place of the date of the event, None once it's over
+    fn label(&self, event: &SEvent, now: u64) -> Option<String> {
+        let start = event.timestamp;
+        if now >= start + self.ongoing_for.as_secs() {
+            None
+        } else if now >= start {
+            Some("in corso".to_string())
+        } else if start - now <= self.countdown_from.as_secs() {
+            let step = self.countdown_step.as_secs().max(60);
+            let minutes = (start - now).div_ceil(step) * step / 60;
+            Some(format!("tra {} min", minutes))
+        } else {
+            Some(event.datetime.clone())
+        }
+    }
+
+    fn event_change(&self, event: &SEvent, now: u64) -> Option<u64> {
+        let start = event.timestamp;
+        let end = start + self.ongoing_for.as_secs();
+        let countdown = start.saturating_sub(self.countdown_from.as_secs());
+        if now >= end {
+            None
+        } else if now >= start {
+            Some(end)
+        } else if now >= countdown {
+            // When the remaining time drops to the previous step
+            let step = self.countdown_step.as_secs().max(60);
+            let steps = (start - now).div_ceil(step);
+            Some(start - (steps - 1) * step)
+        } else {
+            Some(countdown)
+        }
+    }
+}
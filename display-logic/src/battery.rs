@@ -0,0 +1,73 @@
+//! Battery monitoring: the charge left in the LiPo cell powering the board, from its voltage.
+
+/// Resting voltage of a single LiPo cell (mV) and the charge left (%), from full to empty. The discharge curve is
+/// flat in the middle: a few mV make a difference there
+const DISCHARGE_CURVE: [(u32, u8); 21] = [
+    (4200, 100),
+    (4150, 95),
+    (4110, 90),
+    (4080, 85),
+    (4020, 80),
+    (3980, 75),
+    (3950, 70),
+    (3910, 65),
+    (3870, 60),
+    (3850, 55),
+    (3840, 50),
+    (3820, 45),
+    (3800, 40),
+    (3790, 35),
+    (3770, 30),
+    (3750, 25),
+    (3730, 20),
+    (3710, 15),
+    (3690, 10),
+    (3610, 5),
+    (3270, 0),
+];
+
+/// The charge left in the cell (0 to 100%) at `millivolts`, interpolated on the discharge curve
+pub fn percentage(millivolts: u32) -> u8 {
+    let (full, _) = DISCHARGE_CURVE[0];
+    if millivolts >= full {
+        return 100;
+    }
+    for window in DISCHARGE_CURVE.windows(2) {
+        let [(high_mv, high), (low_mv, low)] = [window[0], window[1]];
+        if millivolts >= low_mv {
+            let step = (millivolts - low_mv) * (high - low) as u32 / (high_mv - low_mv);
+            return low + step as u8;
+        }
+    }
+    0
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Level {
+    #[default]
+    Normal,
+    /// Worth recharging soon
+    Low,
+    /// The board stops refreshing the schedule and shows a low battery screen instead, to protect the cell
+    Critical,
+}
+
+/// Charge levels (%) at which the battery is low or critical
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Thresholds {
+    pub low: u8,
+    pub critical: u8,
+}
+
+impl Thresholds {
+    /// The level at `percent`, given the `previous` one. Once critical, the battery stays so until it's back to the low
+    /// threshold (eg. charging): the voltage of a cell recovers a bit at rest, and the screen would keep switching
+    pub fn level(&self, percent: u8, previous: Level) -> Level {
+        match percent {
+            percent if percent <= self.critical => Level::Critical,
+            percent if percent < self.low && previous == Level::Critical => Level::Critical,
+            percent if percent <= self.low => Level::Low,
+            _ => Level::Normal,
+        }
+    }
+}
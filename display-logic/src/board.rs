@@ -0,0 +1,86 @@
+//! The ESP32 boards the firmware runs on, and how the e-paper panel is wired to each of them. The board is a setting,
+//! independent of the panel: any panel of the Waveshare driver HAT can be wired to any board.
+
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Board {
+    /// DFRobot FireBeetle 2 ESP32-E, the battery powered hardware of the displays
+    FireBeetle2,
+    /// The ESP32 DevKit simulated by Wokwi, wired as in diagram.json
+    WokwiDevKit,
+    /// Espressif ESP32-DevKitC, wired to the VSPI bus
+    DevKitC,
+}
+
+/// GPIO numbers of the SPI bus and of the control pins of the panel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PinMap {
+    pub sclk: i32,
+    /// Data to the panel (DIN), the panel never answers on the bus
+    pub mosi: i32,
+    pub cs: i32,
+    pub dc: i32,
+    pub rst: i32,
+    pub busy: i32,
+}
+
+impl PinMap {
+    /// Every pin of the map, the outputs first (BUSY is the only input)
+    pub fn all(&self) -> [i32; 6] {
+        [self.sclk, self.mosi, self.cs, self.dc, self.rst, self.busy]
+    }
+}
+
+impl Board {
+    pub const ALL: [Board; 3] = [Board::FireBeetle2, Board::WokwiDevKit, Board::DevKitC];
+
+    /// Name used in the `board` setting
+    pub fn name(&self) -> &'static str {
+        match self {
+            Board::FireBeetle2 => "firebeetle2",
+            Board::WokwiDevKit => "wokwi_devkit",
+            Board::DevKitC => "devkitc",
+        }
+    }
+
+    pub fn pins(&self) -> PinMap {
+        match self {
+            Board::FireBeetle2 => PinMap {
+                sclk: 18,
+                mosi: 23,
+                cs: 14,
+                dc: 22,
+                rst: 21,
+                busy: 4,
+            },
+            Board::WokwiDevKit => PinMap {
+                sclk: 18,
+                mosi: 23,
+                cs: 5,
+                dc: 22,
+                rst: 21,
+                busy: 4,
+            },
+            Board::DevKitC => PinMap {
+                sclk: 18,
+                mosi: 23,
+                cs: 5,
+                dc: 17,
+                rst: 16,
+                busy: 4,
+            },
+        }
+    }
+}
+
+impl FromStr for Board {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Board::ALL
+            .into_iter()
+            .find(|board| board.name() == s)
+            .ok_or_else(|| format!("Unknown board: {}", s))
+    }
+}
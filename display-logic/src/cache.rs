@@ -0,0 +1,126 @@
+//! The last valid payload of every display topic, saved in flash: after a reboot the schedule is shown right away,
+//! before the network is up, and a payload received again (eg. the retained message at every connection) doesn't
+//! refresh the panel.
+
+use display_payload::payload_hash;
+
+/// Where the payloads are saved, eg. an NVS namespace
+pub trait BlobStore {
+    type Error;
+
+    fn get(&self, key: &str) -> Option<Vec<u8>>;
+    fn set(&mut self, key: &str, value: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// Length of the hex encoded hash of [`payload_hash`]
+const HASH_LEN: usize = 16;
+
+/// A cached payload, with the topic it's been received on
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachedPayload {
+    pub topic: String,
+    pub payload: Vec<u8>,
+}
+
+impl CachedPayload {
+    /// The hash of the payload, then the length of the topic (a byte), the topic and the payload
+    pub fn encode(&self) -> Vec<u8> {
+        let topic = &self.topic.as_bytes()[..self.topic.len().min(u8::MAX as usize)];
+        let mut blob = payload_hash(&self.payload).into_bytes();
+        blob.push(topic.len() as u8);
+        blob.extend_from_slice(topic);
+        blob.extend_from_slice(&self.payload);
+        blob
+    }
+
+    /// Refuses a blob whose payload doesn't match its hash, eg. written only in part before a power loss
+    pub fn decode(blob: &[u8]) -> Option<Self> {
+        let hash = blob.get(..HASH_LEN)?;
+        let topic_len = *blob.get(HASH_LEN)? as usize;
+        let topic = blob.get(HASH_LEN + 1..HASH_LEN + 1 + topic_len)?;
+        let payload = &blob[HASH_LEN + 1 + topic_len..];
+        if hash != payload_hash(payload).as_bytes() {
+            return None;
+        }
+        Some(Self {
+            topic: String::from_utf8(topic.to_vec()).ok()?,
+            payload: payload.to_vec(),
+        })
+    }
+}
+
+/// The cache of the display topics: the payload of each topic is saved under its index, as keys are short (15
+/// characters in NVS) and topics aren't. A payload saved for another topic (the settings changed) is ignored.
+pub struct PayloadCache<S> {
+    store: S,
+    topics: Vec<String>,
+    /// The hash of the payload cached for each topic
+    hashes: Vec<Option<String>>,
+}
+
+impl<S: BlobStore> PayloadCache<S> {
+    pub fn new(store: S, topics: Vec<String>) -> Self {
+        let mut cache = Self {
+            store,
+            hashes: vec![None; topics.len()],
+            topics,
+        };
+        cache.hashes = (0..cache.topics.len())
+            .map(|index| cache.get(index).map(|cached| payload_hash(&cached.payload)))
+            .collect();
+        cache
+    }
+
+    /// The payloads cached for the display topics
+    pub fn payloads(&self) -> Vec<CachedPayload> {
+        (0..self.topics.len()).filter_map(|index| self.get(index)).collect()
+    }
+
+    /// Whether `payload` is the one cached for `topic`
+    pub fn is_cached(&self, topic: &str, payload: &[u8]) -> bool {
+        match self.index(topic) {
+            Some(index) => self.hashes[index].as_deref() == Some(payload_hash(payload).as_str()),
+            None => false,
+        }
+    }
+
+    /// Whether `payload`, received on `topic`, is already on screen and doesn't need a refresh: it's the one cached,
+    /// drawn at boot or when it's been last received. Eg. the retained payload sent again at every connection, also
+    /// after the reboot of an update. `drawn` tells whether the panel shows the cached payloads as they are
+    pub fn is_on_screen(&self, topic: &str, payload: &[u8], drawn: bool) -> bool {
+        drawn && self.is_cached(topic, payload)
+    }
+
+    /// Saves a valid payload of a display topic, unless it's already cached. Returns whether it's been written
+    pub fn save(&mut self, topic: &str, payload: &[u8]) -> Result<bool, S::Error> {
+        let Some(index) = self.index(topic) else {
+            return Ok(false);
+        };
+        if self.is_cached(topic, payload) {
+            return Ok(false);
+        }
+        let cached = CachedPayload {
+            topic: topic.to_string(),
+            payload: payload.to_vec(),
+        };
+        // Until it's written, neither the old payload nor the new one is known to be in the store
+        self.hashes[index] = None;
+        self.store.set(&key(index), &cached.encode())?;
+        self.hashes[index] = Some(payload_hash(payload));
+        Ok(true)
+    }
+
+    fn index(&self, topic: &str) -> Option<usize> {
+        self.topics.iter().position(|candidate| candidate == topic)
+    }
+
+    fn get(&self, index: usize) -> Option<CachedPayload> {
+        let cached = CachedPayload::decode(&self.store.get(&key(index))?)?;
+        (cached.topic == self.topics[index]).then_some(cached)
+    }
+}
+
+/// The key of the payload of the display topic at `index`
+fn key(index: usize) -> String {
+    format!("payload{}", index)
+}
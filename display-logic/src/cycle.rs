@@ -0,0 +1,164 @@
+//! The refresh cycle of a battery powered display: wake up, fetch the retained payloads of the display topics, show
+//! them, and go back to deep sleep until the content is due to change.
+
+use std::{fmt, time::Duration};
+
+use crate::network::Backoff;
+
+/// The drivers used by the refresh cycle.
+pub trait Device {
+    type Error: fmt::Display;
+
+    /// Connects to the Wi-Fi and the MQTT broker, and subscribes to the display topics
+    fn connect(&mut self) -> Result<(), Self::Error>;
+
+    /// Waits for a message on each of the display topics, returning the ones received before the timeout. The broker
+    /// sends the retained ones right after subscribing
+    fn receive(&mut self, timeout: Duration) -> Vec<Vec<u8>>;
+
+    /// Parses the messages and draws them on the panel
+    fn show(&mut self, messages: &[Vec<u8>]) -> Result<Shown, Self::Error>;
+
+    /// Replaces the screen with a notice that the network can't be reached, and when the next attempt will be
+    fn show_unreachable(&mut self, retry_in: Duration) -> Result<(), Self::Error>;
+
+    /// Puts the panel in its low power mode: the e-paper keeps showing the last image
+    fn sleep_panel(&mut self) -> Result<(), Self::Error>;
+
+    /// Current Unix timestamp (seconds), if the clock has been set
+    fn now(&self) -> Option<u64>;
+}
+
+/// What has been drawn on the panel, to know when to wake up next
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Shown {
+    pub generated_at: u64,
+    pub next_change_at: Option<u64>,
+}
+
+/// The steps of a refresh cycle, see [`Config::step`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum State {
+    /// Just woken up
+    Connect,
+    WaitForMessages,
+    Show(Vec<Vec<u8>>),
+    ShowUnreachable(Sleep),
+    SleepPanel(Sleep),
+    /// Nothing left to do until the next wake-up: the board can enter deep sleep
+    DeepSleep(Sleep),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sleep {
+    pub duration: Duration,
+    pub outcome: Outcome,
+}
+
+/// How the cycle went, for the logs
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Shown,
+    /// The Wi-Fi or the broker couldn't be reached
+    Unreachable(String),
+    /// Nothing has been received on the display topics
+    NoMessage,
+    /// The message couldn't be shown, the panel still shows the previous one
+    Rejected(String),
+}
+
+impl Outcome {
+    pub fn is_failure(&self) -> bool {
+        !matches!(self, Outcome::Shown)
+    }
+}
+
+/// Timings of the refresh cycle
+pub struct Config {
+    /// How long to wait for the retained messages after subscribing
+    pub message_timeout: Duration,
+    /// How long to sleep after a failed cycle before trying again, growing with the consecutive failures
+    pub retry: Backoff,
+    /// After how many consecutive cycles without network the schedule on screen is replaced by a notice: until then,
+    /// it's likely still accurate
+    pub unreachable_notice_after: u32,
+    /// Lower bound of the sleep after a successful cycle, so that a stale content change hint doesn't keep the board
+    /// awake
+    pub min_sleep: Duration,
+    /// Upper bound of the sleep after a successful cycle, so that the board still checks in periodically (eg. to catch
+    /// newly created events)
+    pub max_sleep: Duration,
+}
+
+impl Config {
+    /// `failed_cycles` is the number of consecutive failed cycles before this one
+    pub fn step<D: Device>(&self, state: State, device: &mut D, failed_cycles: u32) -> State {
+        match state {
+            State::Connect => match device.connect() {
+                Ok(()) => State::WaitForMessages,
+                Err(e) => {
+                    let sleep = self.retry(Outcome::Unreachable(e.to_string()), failed_cycles);
+                    match failed_cycles + 1 >= self.unreachable_notice_after {
+                        true => State::ShowUnreachable(sleep),
+                        false => State::SleepPanel(sleep),
+                    }
+                }
+            },
+            State::WaitForMessages => match device.receive(self.message_timeout) {
+                messages if messages.is_empty() => State::SleepPanel(self.retry(Outcome::NoMessage, failed_cycles)),
+                messages => State::Show(messages),
+            },
+            State::Show(messages) => match device.show(&messages) {
+                Ok(shown) => State::SleepPanel(Sleep {
+                    duration: self.sleep_after(&shown, device.now()),
+                    outcome: Outcome::Shown,
+                }),
+                Err(e) => State::SleepPanel(self.retry(Outcome::Rejected(e.to_string()), failed_cycles)),
+            },
+            State::ShowUnreachable(sleep) => {
+                // Still worth sleeping the panel and the board if the notice can't be drawn
+                let _ = device.show_unreachable(sleep.duration);
+                State::SleepPanel(sleep)
+            }
+            State::SleepPanel(sleep) => {
+                // The panel is reinitialized on wake-up anyway: a failure here only costs some power
+                let _ = device.sleep_panel();
+                State::DeepSleep(sleep)
+            }
+            State::DeepSleep(sleep) => State::DeepSleep(sleep),
+        }
+    }
+
+    /// Runs a whole cycle, calling `on_state` on every state change (eg. to log it), and returns how long the board
+    /// should deep sleep.
+    /// The board has to keep count of the consecutive failed cycles across deep sleep (eg. in RTC memory).
+    pub fn run<D: Device>(&self, device: &mut D, failed_cycles: u32, mut on_state: impl FnMut(&State)) -> Sleep {
+        let mut state = State::Connect;
+        loop {
+            on_state(&state);
+            state = match state {
+                State::DeepSleep(sleep) => return sleep,
+                state => self.step(state, device, failed_cycles),
+            };
+        }
+    }
+
+    fn retry(&self, outcome: Outcome, failed_cycles: u32) -> Sleep {
+        Sleep {
+            duration: self.retry.delay(failed_cycles + 1),
+            outcome,
+        }
+    }
+
+    /// Sleeps until the content is due to change. Without a clock, the generation time of the payload is the best
+    /// estimate of the current time.
+    fn sleep_after(&self, shown: &Shown, now: Option<u64>) -> Duration {
+        let now = now.unwrap_or(shown.generated_at);
+        match shown.next_change_at {
+            Some(next_change_at) => {
+                Duration::from_secs(next_change_at.saturating_sub(now)).clamp(self.min_sleep, self.max_sleep)
+            }
+            None => self.max_sleep,
+        }
+    }
+}
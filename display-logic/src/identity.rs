@@ -0,0 +1,67 @@
+//! Which topics a display subscribes to, and how their payloads end up on a single screen.
+
+use display_payload::{Envelope, TopicKind};
+
+/// What a display shows, configured with the `building` and `rooms` settings.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Identity {
+    /// Next to the door of a room: only the events of that room
+    Room { building: String, room: String },
+    /// In the lobby: the events of every room of the building
+    Building { building: String },
+    /// In a hallway: the events of the rooms along it, merged on a single screen
+    Hallway { building: String, rooms: Vec<String> },
+}
+
+impl Identity {
+    /// The topics published by dynamodb-to-mqtt for this display, before any encoding prefix (eg. `F3/P6`)
+    pub fn topics(&self) -> Vec<String> {
+        match self {
+            Identity::Room { building, room } => vec![format!("{}/{}", building, room)],
+            Identity::Building { building } => vec![building.clone()],
+            Identity::Hallway { building, rooms } => {
+                rooms.iter().map(|room| format!("{}/{}", building, room)).collect()
+            }
+        }
+    }
+}
+
+/// A client id unique to the board, built from its MAC address: two displays sharing a client id keep kicking each
+/// other out of the broker (AWS IoT error 119)
+pub fn default_client_id(mac: [u8; 6]) -> String {
+    let mac: String = mac.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("display-{}", mac)
+}
+
+/// Merges the payloads of the topics of a display into a single screen: the events of every room sorted by time,
+/// under the name of the building, grouped under the names of their rooms.
+/// The merged payload is as old as the oldest one, and changes as soon as any of them does.
+pub fn merge(envelopes: &[Envelope]) -> Option<Envelope> {
+    let (first, rest) = envelopes.split_first()?;
+    if rest.is_empty() {
+        return Some(first.clone());
+    }
+    let mut events: Vec<_> = envelopes
+        .iter()
+        .flat_map(|envelope| envelope.events.iter().cloned())
+        .collect();
+    // Stable: events at the same time keep the order of the topics
+    events.sort_by_key(|event| event.timestamp);
+    let mut merged = Envelope::new(
+        envelopes.iter().map(|envelope| envelope.generated_at).min()?,
+        TopicKind::Building,
+        first.building.clone(),
+        None,
+        events,
+    );
+    merged.more_events = envelopes.iter().map(|envelope| envelope.more_events).sum();
+    merged.next_change_at = envelopes.iter().filter_map(|envelope| envelope.next_change_at).min();
+    for envelope in envelopes {
+        for room in envelope.room.iter().chain(&envelope.room_names) {
+            if !merged.room_names.iter().any(|known| known.id == room.id) {
+                merged.room_names.push(room.clone());
+            }
+        }
+    }
+    Some(merged)
+}
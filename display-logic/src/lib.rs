@@ -0,0 +1,11 @@
+pub mod agenda;
+pub mod battery;
+pub mod board;
+pub mod cache;
+pub mod cycle;
+pub mod identity;
+pub mod network;
+pub mod ota;
+pub mod refresh;
+pub mod settings;
+pub mod status;
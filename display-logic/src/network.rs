@@ -0,0 +1,43 @@
+//! Connection policy: which access point to use, and how long to wait before trying again.
+
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccessPoint {
+    pub ssid: String,
+    pub pass: String,
+}
+
+/// Weakest security accepted when connecting to an access point
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Security {
+    Open,
+    /// WPA2 personal, or anything stronger (WPA3 personal, or WPA2/WPA3 transition mode)
+    Personal,
+}
+
+impl AccessPoint {
+    /// Without a password the network can only be open, with one it must not be: the board refuses to downgrade to an
+    /// open network impersonating the access point
+    pub fn security(&self) -> Security {
+        match self.pass.is_empty() {
+            true => Security::Open,
+            false => Security::Personal,
+        }
+    }
+}
+
+/// Exponential backoff between failed connection attempts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Backoff {
+    /// How long to wait after `failures` consecutive failures (at least one): the wait doubles after each of them
+    pub fn delay(&self, failures: u32) -> Duration {
+        let factor = 2u32.saturating_pow(failures.saturating_sub(1));
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+}
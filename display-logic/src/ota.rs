@@ -0,0 +1,155 @@
+//! Over-the-air updates: whether an announced [`Release`] should be installed, and whether the downloaded image is
+//! the one that has been signed.
+
+use std::{fmt, time::Duration};
+
+use display_payload::Release;
+use ed25519_dalek::{Signature, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OtaError {
+    /// The release can't be parsed, eg. a malformed version or hash
+    InvalidRelease(String),
+    /// The public key of the firmware isn't a valid Ed25519 key
+    InvalidKey,
+    /// The downloaded image isn't the one announced
+    HashMismatch,
+    /// The hash hasn't been signed by the key of the firmware
+    BadSignature,
+}
+
+impl fmt::Display for OtaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OtaError::InvalidRelease(reason) => write!(f, "Invalid release: {}", reason),
+            OtaError::InvalidKey => write!(f, "Invalid public key"),
+            OtaError::HashMismatch => write!(f, "The image doesn't match the SHA-256 of the release"),
+            OtaError::BadSignature => write!(f, "The release isn't signed by the firmware key"),
+        }
+    }
+}
+
+impl std::error::Error for OtaError {}
+
+/// A `major.minor.patch` version, compared numerically
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version(pub u32, pub u32, pub u32);
+
+impl Version {
+    pub fn parse(version: &str) -> Result<Self, OtaError> {
+        let invalid = || OtaError::InvalidRelease(format!("version {}", version));
+        let mut parts = version.trim().trim_start_matches('v').split('.');
+        let mut part = || -> Result<u32, OtaError> { parts.next().ok_or_else(invalid)?.parse().map_err(|_| invalid()) };
+        let version = Version(part()?, part()?, part()?);
+        match parts.next() {
+            Some(_) => Err(invalid()),
+            None => Ok(version),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+/// Whether the firmware running `current` should install the release: only newer versions are, so that a retained
+/// announcement doesn't make the displays flash the same image at every boot
+pub fn should_update(release: &Release, current: &str) -> Result<bool, OtaError> {
+    Ok(Version::parse(&release.version)? > Version::parse(current)?)
+}
+
+/// The health check of a newly installed firmware, which boots unverified: it passes as soon as the firmware shows a
+/// payload. A failure to do so might as well be a network outage, which isn't the firmware's fault, or a firmware
+/// that broke the Wi-Fi: it's only rolled back after failing for `timeout`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HealthCheck {
+    pub timeout: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Health {
+    /// A payload has been shown: keep the firmware
+    Valid,
+    /// Not yet, try again
+    Unverified,
+    /// Roll back to the previous firmware
+    Failed,
+}
+
+impl HealthCheck {
+    /// `shown` tells whether a payload has been shown, `unverified_for` how long the firmware has been running
+    pub fn check(&self, shown: bool, unverified_for: Duration) -> Health {
+        if shown {
+            Health::Valid
+        } else if unverified_for >= self.timeout {
+            Health::Failed
+        } else {
+            Health::Unverified
+        }
+    }
+}
+
+/// Checks the image while it's being downloaded, chunk by chunk: it's too big to be kept in memory
+pub struct ImageCheck {
+    hasher: Sha256,
+    len: usize,
+}
+
+impl Default for ImageCheck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ImageCheck {
+    pub fn new() -> Self {
+        Self {
+            hasher: Sha256::new(),
+            len: 0,
+        }
+    }
+
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.hasher.update(chunk);
+        self.len += chunk.len();
+    }
+
+    /// How many bytes have been checked so far
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether the image is the one announced by `release`, and the release has been signed by `public_key` for the
+    /// firmware `variant` (see [`Release::signed_message`])
+    pub fn verify(self, release: &Release, variant: &str, public_key: &[u8; 32]) -> Result<(), OtaError> {
+        let sha256 = decode_hex::<32>(&release.sha256).ok_or_else(|| OtaError::InvalidRelease("sha256".to_string()))?;
+        let signature =
+            decode_hex::<64>(&release.signature).ok_or_else(|| OtaError::InvalidRelease("signature".to_string()))?;
+        let key = VerifyingKey::from_bytes(public_key).map_err(|_| OtaError::InvalidKey)?;
+        if self.hasher.finalize().as_slice() != sha256 {
+            return Err(OtaError::HashMismatch);
+        }
+        key.verify_strict(&release.signed_message(variant), &Signature::from_bytes(&signature))
+            .map_err(|_| OtaError::BadSignature)
+    }
+}
+
+/// Parses exactly `N` hex encoded bytes
+pub fn decode_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    let hex = hex.trim().as_bytes();
+    if hex.len() != 2 * N {
+        return None;
+    }
+    let mut bytes = [0; N];
+    for (byte, pair) in bytes.iter_mut().zip(hex.chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(bytes)
+}
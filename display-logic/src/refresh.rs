@@ -0,0 +1,81 @@
+//! How to refresh the e-paper panel. A full refresh flashes the whole panel and uses the most energy, a partial
+//! refresh only redraws the pixels that changed, but leaves some ghosting behind: it's used when a few rows change
+//! (eg. the status bar), with a full refresh every now and then to clear the ghosting.
+
+/// Tallest screen supported, in pixels (the 7.5" panel)
+pub const MAX_ROWS: usize = 480;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Refresh {
+    Full,
+    Partial,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RefreshPolicy {
+    /// After how many consecutive partial refreshes the next one is full
+    pub full_every: u32,
+    /// Largest share of the rows of the screen that can change in a partial refresh, in percent
+    pub max_changed_percent: u32,
+}
+
+/// What's on the panel: a hash of every row of the screen (see `Framebuffer::row_hashes`), and the partial refreshes
+/// since the last full one. Fixed size, so that it can be kept in RTC memory across deep sleep.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PanelState {
+    rows: [u32; MAX_ROWS],
+    /// 0 while what's on the panel isn't known (eg. after a power loss)
+    len: usize,
+    partials: u32,
+}
+
+impl PanelState {
+    pub const UNKNOWN: PanelState = PanelState {
+        rows: [0; MAX_ROWS],
+        len: 0,
+        partials: 0,
+    };
+
+    /// Chooses how to show the screen whose row hashes are `next`, and remembers it as shown.
+    /// A screen replacing an unknown one, or with another size, is always a full refresh.
+    pub fn refresh(&mut self, policy: &RefreshPolicy, next: &[u32]) -> Refresh {
+        let changed = match next.len() == self.len {
+            true => self.rows[..self.len]
+                .iter()
+                .zip(next)
+                .filter(|(shown, next)| shown != next)
+                .count(),
+            false => next.len(),
+        };
+        let refresh = match self.len > 0
+            && self.partials < policy.full_every
+            && changed * 100 <= next.len() * policy.max_changed_percent as usize
+        {
+            true => Refresh::Partial,
+            false => Refresh::Full,
+        };
+        self.partials = match refresh {
+            Refresh::Full => 0,
+            Refresh::Partial => self.partials + 1,
+        };
+        match next.len() <= MAX_ROWS {
+            true => {
+                self.rows[..next.len()].copy_from_slice(next);
+                self.len = next.len();
+            }
+            false => self.len = 0,
+        }
+        refresh
+    }
+
+    /// Partial refreshes since the last full one
+    pub fn partials(&self) -> u32 {
+        self.partials
+    }
+}
+
+impl Default for PanelState {
+    fn default() -> Self {
+        Self::UNKNOWN
+    }
+}
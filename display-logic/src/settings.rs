@@ -0,0 +1,335 @@
+//! The settings of a display, persisted on the board (NVS) and provisioned through the serial console, so that the same
+//! firmware can be flashed on every display.
+//!
+//! The console reads one command per line and answers with lines starting with `OK` or `ERR`:
+//! ```text
+//! set wifi_ssid Aula Magna    -> OK
+//! get wifi_ssid               -> OK Aula Magna
+//! show                        -> wifi_ssid=Aula Magna, ..., then OK
+//! save                        -> OK, once written to the flash
+//! reboot                      -> OK, then the board restarts with the saved settings
+//! ```
+
+use std::fmt;
+
+use display_payload::DisplayModel;
+
+use crate::{board::Board, identity::Identity, network::AccessPoint};
+
+/// Key-value storage of the settings (NVS on the board)
+pub trait Store {
+    type Error: fmt::Display;
+
+    fn get(&self, key: &str) -> Option<String>;
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), Self::Error>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    WifiSsid,
+    WifiPass,
+    WifiSsid2,
+    WifiPass2,
+    WifiSsid3,
+    WifiPass3,
+    MqttEndpoint,
+    MqttClientId,
+    Building,
+    Rooms,
+    Panel,
+    Board,
+}
+
+impl Key {
+    pub const ALL: [Key; 12] = [
+        Key::WifiSsid,
+        Key::WifiPass,
+        Key::WifiSsid2,
+        Key::WifiPass2,
+        Key::WifiSsid3,
+        Key::WifiPass3,
+        Key::MqttEndpoint,
+        Key::MqttClientId,
+        Key::Building,
+        Key::Rooms,
+        Key::Panel,
+        Key::Board,
+    ];
+
+    /// Name used both in the console and as NVS key (at most 15 characters)
+    pub fn name(&self) -> &'static str {
+        match self {
+            Key::WifiSsid => "wifi_ssid",
+            Key::WifiPass => "wifi_pass",
+            Key::WifiSsid2 => "wifi_ssid_2",
+            Key::WifiPass2 => "wifi_pass_2",
+            Key::WifiSsid3 => "wifi_ssid_3",
+            Key::WifiPass3 => "wifi_pass_3",
+            Key::MqttEndpoint => "mqtt_endpoint",
+            Key::MqttClientId => "mqtt_client_id",
+            Key::Building => "building",
+            Key::Rooms => "rooms",
+            Key::Panel => "panel",
+            Key::Board => "board",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Key> {
+        Key::ALL.into_iter().find(|key| key.name() == name)
+    }
+
+    /// Longest value accepted by the drivers
+    fn max_len(&self) -> usize {
+        match self {
+            Key::WifiSsid | Key::WifiSsid2 | Key::WifiSsid3 => 32,
+            Key::WifiPass | Key::WifiPass2 | Key::WifiPass3 => 64,
+            _ => 255,
+        }
+    }
+
+    /// Refuses the values that can't be used, before they're saved
+    fn validate(&self, value: &str) -> Result<(), String> {
+        match self {
+            Key::Panel => value.parse::<DisplayModel>().map(|_| ()),
+            Key::Board => value.parse::<Board>().map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Secrets are never printed back on the console
+    fn is_secret(&self) -> bool {
+        matches!(self, Key::WifiPass | Key::WifiPass2 | Key::WifiPass3)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Settings {
+    /// The preferred access point. An empty password means an open network
+    pub wifi_ssid: String,
+    pub wifi_pass: String,
+    /// Fallback access points, tried in order when the preferred one can't be reached. Unused when the SSID is empty
+    pub wifi_ssid_2: String,
+    pub wifi_pass_2: String,
+    pub wifi_ssid_3: String,
+    pub wifi_pass_3: String,
+    /// For AWS IoT Core, use the mqtts protocol and the -ats endpoint
+    pub mqtt_endpoint: String,
+    /// Must be unique, or else the displays kick each other out of the broker
+    pub mqtt_client_id: String,
+    /// Id of the building the display is in, as used in the topics (eg. `F3`)
+    pub building: String,
+    /// Comma separated ids of the rooms shown by the display (eg. `P6` or `P6,P7`), empty for a building display.
+    /// See [`Identity`]
+    pub rooms: String,
+    /// The e-paper panel wired to the board, by model name (eg. `epd5in83_v2`), see [`Settings::display_model`]
+    pub panel: String,
+    /// The ESP32 board, which decides the pins of the panel (eg. `firebeetle2`), see [`Settings::board`]
+    pub board: String,
+}
+
+impl Settings {
+    /// Reads the settings saved in `store`, using `defaults` for the missing ones
+    pub fn load<S: Store>(store: &S, defaults: Settings) -> Self {
+        let mut settings = defaults;
+        for key in Key::ALL {
+            if let Some(value) = store.get(key.name()) {
+                *settings.field(key) = value;
+            }
+        }
+        settings
+    }
+
+    pub fn save<S: Store>(&self, store: &mut S) -> Result<(), S::Error> {
+        for key in Key::ALL {
+            store.set(key.name(), self.get(key))?;
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: Key) -> &str {
+        match key {
+            Key::WifiSsid => &self.wifi_ssid,
+            Key::WifiPass => &self.wifi_pass,
+            Key::WifiSsid2 => &self.wifi_ssid_2,
+            Key::WifiPass2 => &self.wifi_pass_2,
+            Key::WifiSsid3 => &self.wifi_ssid_3,
+            Key::WifiPass3 => &self.wifi_pass_3,
+            Key::MqttEndpoint => &self.mqtt_endpoint,
+            Key::MqttClientId => &self.mqtt_client_id,
+            Key::Building => &self.building,
+            Key::Rooms => &self.rooms,
+            Key::Panel => &self.panel,
+            Key::Board => &self.board,
+        }
+    }
+
+    fn field(&mut self, key: Key) -> &mut String {
+        match key {
+            Key::WifiSsid => &mut self.wifi_ssid,
+            Key::WifiPass => &mut self.wifi_pass,
+            Key::WifiSsid2 => &mut self.wifi_ssid_2,
+            Key::WifiPass2 => &mut self.wifi_pass_2,
+            Key::WifiSsid3 => &mut self.wifi_ssid_3,
+            Key::WifiPass3 => &mut self.wifi_pass_3,
+            Key::MqttEndpoint => &mut self.mqtt_endpoint,
+            Key::MqttClientId => &mut self.mqtt_client_id,
+            Key::Building => &mut self.building,
+            Key::Rooms => &mut self.rooms,
+            Key::Panel => &mut self.panel,
+            Key::Board => &mut self.board,
+        }
+    }
+
+    /// Whether the display can try to connect: an open network has no password, but always an SSID
+    pub fn is_complete(&self) -> bool {
+        !self.wifi_ssid.is_empty() && !self.mqtt_endpoint.is_empty() && !self.building.is_empty()
+    }
+
+    /// The model of the panel, None if it isn't supported
+    pub fn display_model(&self) -> Option<DisplayModel> {
+        self.panel.trim().parse().ok()
+    }
+
+    /// The board, None if it isn't supported
+    pub fn board(&self) -> Option<Board> {
+        self.board.trim().parse().ok()
+    }
+
+    /// The configured access points, in order of preference
+    pub fn access_points(&self) -> Vec<AccessPoint> {
+        [
+            (&self.wifi_ssid, &self.wifi_pass),
+            (&self.wifi_ssid_2, &self.wifi_pass_2),
+            (&self.wifi_ssid_3, &self.wifi_pass_3),
+        ]
+        .into_iter()
+        .filter(|(ssid, _)| !ssid.is_empty())
+        .map(|(ssid, pass)| AccessPoint {
+            ssid: ssid.clone(),
+            pass: pass.clone(),
+        })
+        .collect()
+    }
+
+    pub fn identity(&self) -> Identity {
+        let building = self.building.trim().to_string();
+        let mut rooms: Vec<String> = self
+            .rooms
+            .split(',')
+            .map(str::trim)
+            .filter(|room| !room.is_empty())
+            .map(str::to_string)
+            .collect();
+        match rooms.len() {
+            0 => Identity::Building { building },
+            1 => Identity::Room {
+                building,
+                room: rooms.remove(0),
+            },
+            _ => Identity::Hallway { building, rooms },
+        }
+    }
+
+    /// The value as printed on the console
+    fn display(&self, key: Key) -> &str {
+        match (key.is_secret(), self.get(key).is_empty()) {
+            (true, false) => "********",
+            _ => self.get(key),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Get(Key),
+    /// The value is the rest of the line, so that it can contain spaces. It can be empty (eg. an open network)
+    Set(Key, String),
+    Show,
+    Save,
+    Reboot,
+    Help,
+}
+
+impl Command {
+    /// Parses a line of the console, `None` for a blank line
+    pub fn parse(line: &str) -> Result<Option<Command>, String> {
+        let line = line.trim_end_matches(['\r', '\n']).trim_start();
+        if line.trim_end().is_empty() {
+            return Ok(None);
+        }
+        let (command, arguments) = line.split_once(' ').unwrap_or((line.trim_end(), ""));
+        let key = || {
+            let name = arguments.split(' ').next().unwrap_or_default();
+            Key::parse(name).ok_or_else(|| format!("unknown setting: {}", name))
+        };
+        let command = match command {
+            "get" => Command::Get(key()?),
+            "set" => {
+                let value = arguments.split_once(' ').map_or("", |(_, value)| value);
+                Command::Set(key()?, value.to_string())
+            }
+            "show" => Command::Show,
+            "save" => Command::Save,
+            "reboot" => Command::Reboot,
+            "help" => Command::Help,
+            command => return Err(format!("unknown command: {}", command)),
+        };
+        Ok(Some(command))
+    }
+}
+
+/// What the console should do after a command
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Response {
+    /// Lines to print
+    Output(Vec<String>),
+    /// Print OK and restart the board, to apply the saved settings
+    Reboot,
+}
+
+/// Runs a line of the console on the settings being edited: they're only persisted by the `save` command.
+pub fn handle_line<S: Store>(line: &str, settings: &mut Settings, store: &mut S) -> Response {
+    let command = match Command::parse(line) {
+        Ok(Some(command)) => command,
+        Ok(None) => return Response::Output(Vec::new()),
+        Err(e) => return Response::Output(vec![format!("ERR {}", e)]),
+    };
+    let lines = match command {
+        Command::Get(key) => vec![format!("OK {}", settings.display(key))],
+        Command::Set(key, value) if value.len() > key.max_len() => {
+            vec![format!(
+                "ERR {} is too long (at most {} bytes)",
+                key.name(),
+                key.max_len()
+            )]
+        }
+        Command::Set(key, value) => match key.validate(&value) {
+            Ok(()) => {
+                *settings.field(key) = value;
+                vec!["OK".to_string()]
+            }
+            Err(e) => vec![format!("ERR {}", e)],
+        },
+        Command::Show => Key::ALL
+            .into_iter()
+            .map(|key| format!("{}={}", key.name(), settings.display(key)))
+            .chain(["OK".to_string()])
+            .collect(),
+        Command::Save => match settings.save(store) {
+            Ok(()) => vec!["OK".to_string()],
+            Err(e) => vec![format!("ERR storage: {}", e)],
+        },
+        Command::Reboot => return Response::Reboot,
+        Command::Help => vec![
+            "get <setting>".to_string(),
+            "set <setting> <value>".to_string(),
+            "show".to_string(),
+            "save".to_string(),
+            "reboot".to_string(),
+            format!("settings: {}", Key::ALL.map(|key| key.name()).join(", ")),
+            "OK".to_string(),
+        ],
+    };
+    Response::Output(lines)
+}
@@ -0,0 +1,58 @@
+//! The status bar of the screen: when the schedule has last been updated, and how the board is doing.
+
+use std::time::Duration;
+
+use display_layout::StatusBar;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Status {
+    /// When the last payload has been received (Unix timestamp), if the clock was set by then
+    pub synced_at: Option<u64>,
+    /// Signal strength of the access point, in dBm
+    pub rssi: Option<i8>,
+    /// Battery level, in percent
+    pub battery: Option<u8>,
+}
+
+impl Status {
+    /// Whether the schedule is older than `stale_after`: it might have changed since. Without a clock it can't be told
+    pub fn is_stale(&self, now: Option<u64>, stale_after: Duration) -> bool {
+        match (self.synced_at, now) {
+            (Some(synced_at), Some(now)) => now.saturating_sub(synced_at) >= stale_after.as_secs(),
+            _ => false,
+        }
+    }
+
+    /// The texts of the status bar. `local_time` formats a timestamp as the time of the day (eg. `10:42`)
+    pub fn bar(&self, now: Option<u64>, stale_after: Duration, local_time: impl Fn(u64) -> String) -> StatusBar {
+        let warning = self.is_stale(now, stale_after);
+        let left = match (self.synced_at, now) {
+            (Some(synced_at), Some(now)) if warning => {
+                format!("Non aggiornato da {}", format_age(Duration::from_secs(now - synced_at)))
+            }
+            (Some(synced_at), _) => format!("Aggiornato {}", local_time(synced_at)),
+            (None, _) => String::new(),
+        };
+        let right: Vec<String> = [
+            self.rssi.map(|rssi| format!("{} dBm", rssi)),
+            self.battery.map(|battery| format!("{}%", battery)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        StatusBar {
+            left,
+            right: right.join(" "),
+            warning,
+        }
+    }
+}
+
+/// A rough duration, eg. `3 h`
+pub fn format_age(age: Duration) -> String {
+    match age.as_secs() {
+        secs if secs < 60 * 60 => format!("{} min", secs / 60),
+        secs if secs < 48 * 60 * 60 => format!("{} h", secs / (60 * 60)),
+        secs => format!("{} giorni", secs / (24 * 60 * 60)),
+    }
+}
@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use display_logic::agenda::Agenda;
+use display_payload::{Envelope, Place, SEvent, TopicKind};
+
+const AGENDA: Agenda = Agenda {
+    ongoing_for: Duration::from_secs(60 * 60),
+    countdown_from: Duration::from_secs(60 * 60),
+    countdown_step: Duration::from_secs(10 * 60),
+};
+// 2023-07-07 10:00 UTC
+const NOW: u64 = 1688724000;
+
+fn event(title: &str, timestamp: u64) -> SEvent {
+    SEvent {
+        id: title.to_string(),
+        title: title.to_string(),
+        timestamp,
+        datetime: "2023-07-07 12:00".to_string(),
+        building: "F3".to_string(),
+        room: "P6".to_string(),
+    }
+}
+
+fn envelope(events: Vec<SEvent>) -> Envelope {
+    Envelope::new(
+        NOW - 3 * 60 * 60,
+        TopicKind::Room,
+        Place {
+            id: "F3".to_string(),
+            name: "Edificio F3".to_string(),
+        },
+        Some(Place {
+            id: "P6".to_string(),
+            name: "Aula P6".to_string(),
+        }),
+        events,
+    )
+}
+
+fn labels(envelope: &Envelope) -> Vec<(&str, &str)> {
+    envelope
+        .events
+        .iter()
+        .map(|event| (event.title.as_str(), event.datetime.as_str()))
+        .collect()
+}
+
+#[test]
+fn hides_past_events_and_labels_the_next_ones() {
+    let envelope = envelope(vec![
+        event("Finito", NOW - 2 * 60 * 60),
+        event("Lezione", NOW - 20 * 60),
+        event("Seminario", NOW + 15 * 60),
+        event("Esame", NOW + 2 * 60 * 60),
+    ]);
+    assert_eq!(
+        labels(&AGENDA.at(&envelope, NOW)),
+        [
+            ("Lezione", "in corso"),
+            ("Seminario", "tra 20 min"),
+            ("Esame", "2023-07-07 12:00")
+        ]
+    );
+}
+
+#[test]
+fn countdown_is_rounded_up() {
+    let envelope = envelope(vec![event("Seminario", NOW + 60 * 60)]);
+    assert_eq!(labels(&AGENDA.at(&envelope, NOW))[0].1, "tra 60 min");
+    assert_eq!(labels(&AGENDA.at(&envelope, NOW + 1))[0].1, "tra 60 min");
+    assert_eq!(labels(&AGENDA.at(&envelope, NOW + 10 * 60))[0].1, "tra 50 min");
+    assert_eq!(labels(&AGENDA.at(&envelope, NOW + 59 * 60))[0].1, "tra 10 min");
+}
+
+#[test]
+fn next_change() {
+    // The countdown starts an hour before
+    let envelope = envelope(vec![event("Esame", NOW + 2 * 60 * 60)]);
+    assert_eq!(AGENDA.next_change(&envelope, NOW), Some(NOW + 60 * 60));
+    // From 25 to 20 minutes left
+    assert_eq!(AGENDA.next_change(&envelope, NOW + 95 * 60), Some(NOW + 100 * 60));
+    // Starts, then ends
+    assert_eq!(AGENDA.next_change(&envelope, NOW + 115 * 60), Some(NOW + 120 * 60));
+    assert_eq!(AGENDA.next_change(&envelope, NOW + 120 * 60), Some(NOW + 180 * 60));
+    assert_eq!(AGENDA.next_change(&envelope, NOW + 180 * 60), None);
+}
+
+#[test]
+fn every_change_is_seen() {
+    // The labels only change at the moments announced by next_change
+    let envelope = envelope(vec![
+        event("Lezione", NOW + 7 * 60),
+        event("Seminario", NOW + 43 * 60),
+        event("Esame", NOW + 3 * 60 * 60),
+    ]);
+    let mut now = NOW;
+    while let Some(next_change) = AGENDA.next_change(&envelope, now) {
+        assert!(next_change > now);
+        let shown = AGENDA.at(&envelope, now);
+        for time in now..next_change {
+            assert_eq!(
+                AGENDA.at(&envelope, time),
+                shown,
+                "changed at {} before {}",
+                time,
+                next_change
+            );
+        }
+        assert_ne!(AGENDA.at(&envelope, next_change), shown);
+        now = next_change;
+    }
+    assert!(AGENDA.at(&envelope, now).events.is_empty());
+}
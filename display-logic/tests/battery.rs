@@ -0,0 +1,36 @@
+use display_logic::battery::{percentage, Level, Thresholds};
+
+const THRESHOLDS: Thresholds = Thresholds { low: 20, critical: 5 };
+
+#[test]
+fn percentage_follows_the_discharge_curve() {
+    assert_eq!(percentage(4250), 100);
+    assert_eq!(percentage(4200), 100);
+    assert_eq!(percentage(3840), 50);
+    // Halfway between 3730 mV (20%) and 3750 mV (25%)
+    assert_eq!(percentage(3740), 22);
+    assert_eq!(percentage(3270), 0);
+    assert_eq!(percentage(3000), 0);
+}
+
+#[test]
+fn percentage_never_decreases_with_voltage() {
+    let percentages: Vec<u8> = (3000..4300).map(percentage).collect();
+    assert!(percentages.windows(2).all(|pair| pair[0] <= pair[1]));
+}
+
+#[test]
+fn levels() {
+    assert_eq!(THRESHOLDS.level(80, Level::Normal), Level::Normal);
+    assert_eq!(THRESHOLDS.level(20, Level::Normal), Level::Low);
+    assert_eq!(THRESHOLDS.level(5, Level::Low), Level::Critical);
+}
+
+#[test]
+fn critical_until_recharged() {
+    // The voltage recovers a bit at rest
+    assert_eq!(THRESHOLDS.level(8, Level::Critical), Level::Critical);
+    assert_eq!(THRESHOLDS.level(8, Level::Low), Level::Low);
+    assert_eq!(THRESHOLDS.level(20, Level::Critical), Level::Low);
+    assert_eq!(THRESHOLDS.level(21, Level::Critical), Level::Normal);
+}
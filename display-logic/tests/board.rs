@@ -0,0 +1,30 @@
+use display_logic::board::Board;
+
+#[test]
+fn board_names() {
+    for board in Board::ALL {
+        assert_eq!(board.name().parse(), Ok(board));
+    }
+    assert_eq!(
+        "firebeetle".parse::<Board>(),
+        Err("Unknown board: firebeetle".to_string())
+    );
+}
+
+#[test]
+fn pins_can_drive_the_panel() {
+    for board in Board::ALL {
+        let pins = board.pins().all();
+        for (i, pin) in pins.iter().enumerate() {
+            assert!(!pins[..i].contains(pin), "{:?} uses GPIO{} twice", board, pin);
+            // GPIO0 is the boot button (next page), 6 to 11 are wired to the flash
+            assert!(*pin != 0 && !(6..=11).contains(pin), "{:?} uses GPIO{}", board, pin);
+        }
+        // 34 to 39 are input only
+        assert!(
+            pins[..5].iter().all(|pin| *pin < 34),
+            "{:?} drives an input only pin",
+            board
+        );
+    }
+}
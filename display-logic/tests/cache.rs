@@ -0,0 +1,100 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use display_logic::cache::{BlobStore, CachedPayload, PayloadCache};
+
+/// Shared between the caches of a test, as the flash is across reboots
+#[derive(Clone, Default)]
+struct Flash(Rc<RefCell<HashMap<String, Vec<u8>>>>);
+
+impl BlobStore for Flash {
+    type Error = ();
+
+    fn get(&self, key: &str) -> Option<Vec<u8>> {
+        self.0.borrow().get(key).cloned()
+    }
+
+    fn set(&mut self, key: &str, value: &[u8]) -> Result<(), ()> {
+        self.0.borrow_mut().insert(key.to_string(), value.to_vec());
+        Ok(())
+    }
+}
+
+fn topics() -> Vec<String> {
+    vec!["F3/P6".to_string(), "F3/P7".to_string()]
+}
+
+#[test]
+fn blob_roundtrip() {
+    let cached = CachedPayload {
+        topic: "postcard/F3/P6".to_string(),
+        payload: b"\x01\x02 payload".to_vec(),
+    };
+    assert_eq!(CachedPayload::decode(&cached.encode()), Some(cached.clone()));
+
+    // Written in part, or corrupted
+    let blob = cached.encode();
+    assert_eq!(CachedPayload::decode(&blob[..blob.len() - 1]), None);
+    assert_eq!(CachedPayload::decode(&blob[..10]), None);
+    let mut corrupted = blob.clone();
+    *corrupted.last_mut().unwrap() ^= 1;
+    assert_eq!(CachedPayload::decode(&corrupted), None);
+    assert_eq!(CachedPayload::decode(&[]), None);
+}
+
+#[test]
+fn payloads_survive_a_reboot() {
+    let flash = Flash::default();
+    let mut cache = PayloadCache::new(flash.clone(), topics());
+    assert!(cache.payloads().is_empty());
+    assert_eq!(cache.save("F3/P7", b"P7 schedule"), Ok(true));
+    assert!(cache.is_cached("F3/P7", b"P7 schedule"));
+    assert!(!cache.is_cached("F3/P6", b"P7 schedule"));
+
+    let cache = PayloadCache::new(flash.clone(), topics());
+    assert_eq!(
+        cache.payloads(),
+        vec![CachedPayload {
+            topic: "F3/P7".to_string(),
+            payload: b"P7 schedule".to_vec(),
+        }]
+    );
+    assert!(cache.is_cached("F3/P7", b"P7 schedule"));
+}
+
+#[test]
+fn a_payload_sent_again_after_a_reboot_is_on_screen() {
+    let flash = Flash::default();
+    let mut cache = PayloadCache::new(flash.clone(), topics());
+    cache.save("F3/P6", b"P6 schedule").unwrap();
+    // Rebooted, eg. on a new firmware: the cached payload is drawn at boot, then the broker sends it again
+    let cache = PayloadCache::new(flash.clone(), topics());
+    assert!(cache.is_on_screen("F3/P6", b"P6 schedule", true));
+    assert!(!cache.is_on_screen("F3/P6", b"new schedule", true));
+    assert!(!cache.is_on_screen("F3/P7", b"P6 schedule", true));
+    // Eg. the panel failed at boot, or a notice covers the schedule
+    assert!(!cache.is_on_screen("F3/P6", b"P6 schedule", false));
+}
+
+#[test]
+fn unchanged_payloads_are_not_written() {
+    let flash = Flash::default();
+    let mut cache = PayloadCache::new(flash.clone(), topics());
+    assert_eq!(cache.save("F3/P6", b"first"), Ok(true));
+    assert_eq!(cache.save("F3/P6", b"first"), Ok(false));
+    assert_eq!(cache.save("F3/P6", b"second"), Ok(true));
+    assert!(!cache.is_cached("F3/P6", b"first"));
+    // Not a display topic
+    assert_eq!(cache.save("F3", b"first"), Ok(false));
+    assert_eq!(flash.0.borrow().len(), 1);
+}
+
+#[test]
+fn other_topics_are_ignored() {
+    let flash = Flash::default();
+    let mut cache = PayloadCache::new(flash.clone(), topics());
+    cache.save("F3/P6", b"P6 schedule").unwrap();
+    // The rooms setting changed: the first topic is another one
+    let cache = PayloadCache::new(flash.clone(), vec!["F3/P8".to_string()]);
+    assert!(cache.payloads().is_empty());
+    assert!(!cache.is_cached("F3/P8", b"P6 schedule"));
+}
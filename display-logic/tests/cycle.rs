@@ -0,0 +1,195 @@
+use std::time::Duration;
+
+use display_logic::{
+    cycle::{Config, Device, Outcome, Shown, Sleep, State},
+    network::Backoff,
+};
+
+const CONFIG: Config = Config {
+    message_timeout: Duration::from_secs(30),
+    retry: Backoff {
+        initial: Duration::from_secs(5 * 60),
+        max: Duration::from_secs(60 * 60),
+    },
+    unreachable_notice_after: 3,
+    min_sleep: Duration::from_secs(60),
+    max_sleep: Duration::from_secs(60 * 60),
+};
+// 2023-07-07 10:00 UTC
+const NOW: u64 = 1688724000;
+
+/// Records what the cycle asked the drivers to do
+#[derive(Default)]
+struct MockDevice {
+    unreachable: bool,
+    messages: Vec<Vec<u8>>,
+    shown: Option<Shown>,
+    clock: Option<u64>,
+    calls: Vec<&'static str>,
+}
+
+impl Device for MockDevice {
+    type Error = String;
+
+    fn connect(&mut self) -> Result<(), String> {
+        self.calls.push("connect");
+        match self.unreachable {
+            true => Err("no access point".to_string()),
+            false => Ok(()),
+        }
+    }
+
+    fn receive(&mut self, timeout: Duration) -> Vec<Vec<u8>> {
+        assert_eq!(timeout, CONFIG.message_timeout);
+        self.calls.push("receive");
+        std::mem::take(&mut self.messages)
+    }
+
+    fn show(&mut self, _messages: &[Vec<u8>]) -> Result<Shown, String> {
+        self.calls.push("show");
+        self.shown.ok_or_else(|| "malformed payload".to_string())
+    }
+
+    fn show_unreachable(&mut self, retry_in: Duration) -> Result<(), String> {
+        assert!(retry_in >= CONFIG.retry.initial);
+        self.calls.push("show_unreachable");
+        Ok(())
+    }
+
+    fn sleep_panel(&mut self) -> Result<(), String> {
+        self.calls.push("sleep_panel");
+        Ok(())
+    }
+
+    fn now(&self) -> Option<u64> {
+        self.clock
+    }
+}
+
+fn shown(next_change_at: Option<u64>) -> Option<Shown> {
+    Some(Shown {
+        generated_at: NOW - 60,
+        next_change_at,
+    })
+}
+
+#[test]
+fn sleeps_until_next_change() {
+    let mut device = MockDevice {
+        messages: vec![b"payload".to_vec()],
+        shown: shown(Some(NOW + 20 * 60)),
+        clock: Some(NOW),
+        ..Default::default()
+    };
+    let mut states = Vec::new();
+    let sleep = CONFIG.run(&mut device, 0, |state| states.push(state.clone()));
+    assert_eq!(
+        sleep,
+        Sleep {
+            duration: Duration::from_secs(20 * 60),
+            outcome: Outcome::Shown
+        }
+    );
+    assert_eq!(device.calls, ["connect", "receive", "show", "sleep_panel"]);
+    assert_eq!(
+        states[..3],
+        [
+            State::Connect,
+            State::WaitForMessages,
+            State::Show(vec![b"payload".to_vec()])
+        ]
+    );
+    assert_eq!(states[4], State::DeepSleep(sleep));
+}
+
+#[test]
+fn sleep_is_capped() {
+    let mut device = MockDevice {
+        messages: vec![b"payload".to_vec()],
+        shown: shown(Some(NOW + 24 * 60 * 60)),
+        clock: Some(NOW),
+        ..Default::default()
+    };
+    assert_eq!(CONFIG.run(&mut device, 0, |_| {}).duration, CONFIG.max_sleep);
+
+    // No known change: check in again after the longest sleep
+    device.messages = vec![b"payload".to_vec()];
+    device.shown = shown(None);
+    assert_eq!(CONFIG.run(&mut device, 0, |_| {}).duration, CONFIG.max_sleep);
+
+    // The change is already past (eg. the server hasn't republished yet): don't wake up right away
+    device.messages = vec![b"payload".to_vec()];
+    device.shown = shown(Some(NOW - 60));
+    assert_eq!(CONFIG.run(&mut device, 0, |_| {}).duration, CONFIG.min_sleep);
+}
+
+#[test]
+fn without_clock_payload_time_is_used() {
+    let mut device = MockDevice {
+        messages: vec![b"payload".to_vec()],
+        shown: shown(Some(NOW + 20 * 60)),
+        clock: None,
+        ..Default::default()
+    };
+    assert_eq!(
+        CONFIG.run(&mut device, 0, |_| {}).duration,
+        Duration::from_secs(21 * 60)
+    );
+}
+
+#[test]
+fn retries_when_unreachable() {
+    let mut device = MockDevice {
+        unreachable: true,
+        ..Default::default()
+    };
+    let sleep = CONFIG.run(&mut device, 0, |_| {});
+    assert_eq!(sleep.duration, CONFIG.retry.initial);
+    assert_eq!(sleep.outcome, Outcome::Unreachable("no access point".to_string()));
+    // The panel is put to sleep on every path
+    assert_eq!(device.calls, ["connect", "sleep_panel"]);
+}
+
+#[test]
+fn backs_off_and_shows_notice_when_unreachable() {
+    let mut device = MockDevice {
+        unreachable: true,
+        ..Default::default()
+    };
+    // The schedule stays on screen for the first failures
+    assert_eq!(
+        CONFIG.run(&mut device, 1, |_| {}).duration,
+        Duration::from_secs(10 * 60)
+    );
+    assert_eq!(device.calls, ["connect", "sleep_panel"]);
+
+    device.calls.clear();
+    assert_eq!(
+        CONFIG.run(&mut device, 2, |_| {}).duration,
+        Duration::from_secs(20 * 60)
+    );
+    assert_eq!(device.calls, ["connect", "show_unreachable", "sleep_panel"]);
+
+    assert_eq!(CONFIG.run(&mut device, 10, |_| {}).duration, CONFIG.retry.max);
+}
+
+#[test]
+fn retries_without_message() {
+    let mut device = MockDevice::default();
+    let sleep = CONFIG.run(&mut device, 0, |_| {});
+    assert_eq!(sleep.duration, CONFIG.retry.initial);
+    assert_eq!(sleep.outcome, Outcome::NoMessage);
+    assert_eq!(device.calls, ["connect", "receive", "sleep_panel"]);
+}
+
+#[test]
+fn retries_on_rejected_message() {
+    let mut device = MockDevice {
+        messages: vec![b"garbage".to_vec()],
+        ..Default::default()
+    };
+    let sleep = CONFIG.run(&mut device, 0, |_| {});
+    assert_eq!(sleep.duration, CONFIG.retry.initial);
+    assert_eq!(sleep.outcome, Outcome::Rejected("malformed payload".to_string()));
+    assert_eq!(device.calls, ["connect", "receive", "show", "sleep_panel"]);
+}
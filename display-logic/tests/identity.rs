@@ -0,0 +1,83 @@
+use display_logic::identity::{default_client_id, merge, Identity};
+use display_payload::{Envelope, Place, SEvent, TopicKind};
+
+fn room_envelope(room: &str, generated_at: u64, timestamps: &[u64]) -> Envelope {
+    let events = timestamps
+        .iter()
+        .map(|timestamp| SEvent {
+            id: format!("{}-{}", room, timestamp),
+            title: format!("Evento in {}", room),
+            timestamp: *timestamp,
+            datetime: String::new(),
+            building: "F3".to_string(),
+            room: room.to_string(),
+        })
+        .collect();
+    let mut envelope = Envelope::new(
+        generated_at,
+        TopicKind::Room,
+        Place {
+            id: "F3".to_string(),
+            name: "Edificio F3".to_string(),
+        },
+        Some(Place {
+            id: room.to_string(),
+            name: format!("Aula {}", room),
+        }),
+        events,
+    );
+    envelope.next_change_at = timestamps.first().copied();
+    envelope
+}
+
+#[test]
+fn topics() {
+    let room = Identity::Room {
+        building: "F3".to_string(),
+        room: "P6".to_string(),
+    };
+    assert_eq!(room.topics(), ["F3/P6"]);
+    let building = Identity::Building {
+        building: "F3".to_string(),
+    };
+    assert_eq!(building.topics(), ["F3"]);
+    let hallway = Identity::Hallway {
+        building: "F3".to_string(),
+        rooms: vec!["P6".to_string(), "P7".to_string()],
+    };
+    assert_eq!(hallway.topics(), ["F3/P6", "F3/P7"]);
+}
+
+#[test]
+fn client_id_from_mac() {
+    assert_eq!(
+        default_client_id([0x24, 0x0a, 0xc4, 0x00, 0x01, 0xff]),
+        "display-240ac40001ff"
+    );
+}
+
+#[test]
+fn single_payload_untouched() {
+    let envelope = room_envelope("P6", 100, &[300, 500]);
+    assert_eq!(merge(std::slice::from_ref(&envelope)), Some(envelope));
+    assert_eq!(merge(&[]), None);
+}
+
+#[test]
+fn merges_rooms() {
+    let mut p6 = room_envelope("P6", 100, &[300, 500]);
+    p6.more_events = 2;
+    let mut p7 = room_envelope("P7", 50, &[200, 400]);
+    p7.more_events = 1;
+    let merged = merge(&[p6, p7]).unwrap();
+    assert_eq!(merged.topic_kind, TopicKind::Building);
+    assert_eq!(merged.building.name, "Edificio F3");
+    assert_eq!(merged.room, None);
+    let timestamps: Vec<u64> = merged.events.iter().map(|event| event.timestamp).collect();
+    assert_eq!(timestamps, [200, 300, 400, 500]);
+    assert_eq!(merged.more_events, 3);
+    assert_eq!(merged.generated_at, 50);
+    assert_eq!(merged.next_change_at, Some(200));
+    assert_eq!(merged.room_name("P6"), "Aula P6");
+    assert_eq!(merged.room_name("P7"), "Aula P7");
+}
@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use display_logic::{
+    network::{AccessPoint, Backoff, Security},
+    settings::Settings,
+};
+
+#[test]
+fn access_points_in_order() {
+    let settings = Settings {
+        wifi_ssid: "Aula Magna".to_string(),
+        wifi_pass: "hunter2".to_string(),
+        wifi_ssid_3: "Wokwi-GUEST".to_string(),
+        ..Default::default()
+    };
+    let access_points = settings.access_points();
+    assert_eq!(
+        access_points,
+        [
+            AccessPoint {
+                ssid: "Aula Magna".to_string(),
+                pass: "hunter2".to_string()
+            },
+            AccessPoint {
+                ssid: "Wokwi-GUEST".to_string(),
+                pass: String::new()
+            }
+        ]
+    );
+    assert_eq!(access_points[0].security(), Security::Personal);
+    assert_eq!(access_points[1].security(), Security::Open);
+}
+
+#[test]
+fn backoff_doubles_up_to_max() {
+    let backoff = Backoff {
+        initial: Duration::from_secs(1),
+        max: Duration::from_secs(60),
+    };
+    let delays: Vec<u64> = (1..=8).map(|failures| backoff.delay(failures).as_secs()).collect();
+    assert_eq!(delays, [1, 2, 4, 8, 16, 32, 60, 60]);
+    assert_eq!(backoff.delay(0), backoff.initial);
+    assert_eq!(backoff.delay(u32::MAX), backoff.max);
+}
@@ -0,0 +1,137 @@
+use std::time::Duration;
+
+use display_logic::ota::{decode_hex, should_update, Health, HealthCheck, ImageCheck, OtaError, Version};
+use display_payload::Release;
+use ed25519_dalek::{Signer, SigningKey};
+use sha2::{Digest, Sha256};
+
+const IMAGE: &[u8] = b"\xe9 not really an ESP32 image, but long enough to be split in chunks";
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn signing_key() -> SigningKey {
+    SigningKey::from_bytes(&[7; 32])
+}
+
+const VARIANT: &str = "display";
+
+fn release(image: &[u8], key: &SigningKey) -> Release {
+    let sha256 = Sha256::digest(image);
+    let mut release = Release {
+        version: "0.2.0".to_string(),
+        url: "http://192.168.1.10:8000/rmqtt-0.2.0.bin".to_string(),
+        sha256: hex(&sha256),
+        signature: String::new(),
+    };
+    release.signature = hex(&key.sign(&release.signed_message(VARIANT)).to_bytes());
+    release
+}
+
+fn check(image: &[u8]) -> ImageCheck {
+    let mut check = ImageCheck::new();
+    for chunk in image.chunks(16) {
+        check.update(chunk);
+    }
+    check
+}
+
+#[test]
+fn versions() {
+    assert_eq!(Version::parse("0.10.2"), Ok(Version(0, 10, 2)));
+    assert_eq!(Version::parse("v1.0.0"), Ok(Version(1, 0, 0)));
+    assert!(Version::parse("1.0").is_err());
+    assert!(Version::parse("1.0.0.1").is_err());
+    assert!(Version::parse("1.0.x").is_err());
+    assert!(Version(0, 10, 0) > Version(0, 9, 9));
+}
+
+#[test]
+fn only_newer_releases_are_installed() {
+    let release = release(IMAGE, &signing_key());
+    assert_eq!(should_update(&release, "0.1.0"), Ok(true));
+    assert_eq!(should_update(&release, "0.2.0"), Ok(false));
+    assert_eq!(should_update(&release, "0.10.0"), Ok(false));
+}
+
+#[test]
+fn accepts_the_signed_image() {
+    let key = signing_key();
+    let check = check(IMAGE);
+    assert_eq!(check.len(), IMAGE.len());
+    assert_eq!(
+        check.verify(&release(IMAGE, &key), VARIANT, key.verifying_key().as_bytes()),
+        Ok(())
+    );
+}
+
+#[test]
+fn rejects_a_different_image() {
+    let key = signing_key();
+    let mut image = IMAGE.to_vec();
+    image[3] ^= 1;
+    assert_eq!(
+        check(&image).verify(&release(IMAGE, &key), VARIANT, key.verifying_key().as_bytes()),
+        Err(OtaError::HashMismatch)
+    );
+}
+
+#[test]
+fn rejects_a_release_signed_by_another_key() {
+    let key = signing_key();
+    let other = SigningKey::from_bytes(&[8; 32]);
+    assert_eq!(
+        check(IMAGE).verify(&release(IMAGE, &other), VARIANT, key.verifying_key().as_bytes()),
+        Err(OtaError::BadSignature)
+    );
+    // A matching hash isn't enough: anyone can compute it
+    let mut release = release(IMAGE, &key);
+    release.signature = "00".repeat(64);
+    assert_eq!(
+        check(IMAGE).verify(&release, VARIANT, key.verifying_key().as_bytes()),
+        Err(OtaError::BadSignature)
+    );
+}
+
+#[test]
+fn rejects_a_tampered_release() {
+    let key = signing_key();
+    // The image of another variant
+    assert_eq!(
+        check(IMAGE).verify(&release(IMAGE, &key), "other", key.verifying_key().as_bytes()),
+        Err(OtaError::BadSignature)
+    );
+    // An old image, validly signed, announced as a newer version to force a downgrade
+    let mut release = release(IMAGE, &key);
+    release.version = "9.0.0".to_string();
+    assert_eq!(
+        check(IMAGE).verify(&release, VARIANT, key.verifying_key().as_bytes()),
+        Err(OtaError::BadSignature)
+    );
+}
+
+#[test]
+fn rejects_malformed_releases() {
+    let key = signing_key();
+    let mut release = release(IMAGE, &key);
+    release.sha256.pop();
+    assert!(matches!(
+        check(IMAGE).verify(&release, VARIANT, key.verifying_key().as_bytes()),
+        Err(OtaError::InvalidRelease(_))
+    ));
+    assert_eq!(decode_hex::<2>("0aFf"), Some([0x0a, 0xff]));
+    assert_eq!(decode_hex::<2>("0aFg"), None);
+}
+
+#[test]
+fn network_outages_get_time_before_a_rollback() {
+    let check = HealthCheck {
+        timeout: Duration::from_secs(30 * 60),
+    };
+    assert_eq!(check.check(false, Duration::from_secs(60)), Health::Unverified);
+    assert_eq!(check.check(false, Duration::from_secs(29 * 60)), Health::Unverified);
+    assert_eq!(check.check(false, Duration::from_secs(30 * 60)), Health::Failed);
+    // Late, but shown
+    assert_eq!(check.check(true, Duration::from_secs(60 * 60)), Health::Valid);
+}
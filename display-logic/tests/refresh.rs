@@ -0,0 +1,56 @@
+use display_logic::refresh::{PanelState, Refresh, RefreshPolicy, MAX_ROWS};
+
+const POLICY: RefreshPolicy = RefreshPolicy {
+    full_every: 3,
+    max_changed_percent: 25,
+};
+
+/// 100 rows, `changed` of them different from `screen(0)`
+fn screen(changed: usize) -> Vec<u32> {
+    (0..100)
+        .map(|row| if row < changed { row as u32 + 1000 } else { row as u32 })
+        .collect()
+}
+
+#[test]
+fn first_screen_is_full() {
+    let mut state = PanelState::UNKNOWN;
+    assert_eq!(state.refresh(&POLICY, &screen(0)), Refresh::Full);
+    assert_eq!(state.refresh(&POLICY, &screen(0)), Refresh::Partial);
+}
+
+#[test]
+fn partial_when_few_rows_change() {
+    let mut state = PanelState::UNKNOWN;
+    state.refresh(&POLICY, &screen(0));
+    assert_eq!(state.refresh(&POLICY, &screen(25)), Refresh::Partial);
+    // Compared with what's on screen, not with the first screen
+    assert_eq!(state.refresh(&POLICY, &screen(0)), Refresh::Partial);
+    let mut state = PanelState::UNKNOWN;
+    state.refresh(&POLICY, &screen(0));
+    assert_eq!(state.refresh(&POLICY, &screen(26)), Refresh::Full);
+}
+
+#[test]
+fn full_every_few_partials() {
+    let mut state = PanelState::UNKNOWN;
+    assert_eq!(state.refresh(&POLICY, &screen(0)), Refresh::Full);
+    for partials in 1..=3 {
+        assert_eq!(state.refresh(&POLICY, &screen(partials)), Refresh::Partial);
+        assert_eq!(state.partials(), partials as u32);
+    }
+    assert_eq!(state.refresh(&POLICY, &screen(0)), Refresh::Full);
+    assert_eq!(state.partials(), 0);
+    assert_eq!(state.refresh(&POLICY, &screen(1)), Refresh::Partial);
+}
+
+#[test]
+fn other_size_is_full() {
+    let mut state = PanelState::UNKNOWN;
+    state.refresh(&POLICY, &screen(0));
+    assert_eq!(state.refresh(&POLICY, &screen(0)[..50]), Refresh::Full);
+    // Too tall to be remembered
+    let tall = vec![0; MAX_ROWS + 1];
+    assert_eq!(state.refresh(&POLICY, &tall), Refresh::Full);
+    assert_eq!(state.refresh(&POLICY, &tall), Refresh::Full);
+}
@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+
+use display_logic::{
+    board::Board,
+    identity::Identity,
+    settings::{handle_line, Command, Key, Response, Settings, Store},
+};
+use display_payload::DisplayModel;
+
+/// In-memory NVS
+#[derive(Default)]
+struct MockStore {
+    values: HashMap<String, String>,
+    full: bool,
+}
+
+impl Store for MockStore {
+    type Error = String;
+
+    fn get(&self, key: &str) -> Option<String> {
+        self.values.get(key).cloned()
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        if self.full {
+            return Err("not enough space".to_string());
+        }
+        self.values.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+}
+
+fn defaults() -> Settings {
+    Settings {
+        wifi_ssid: "Wokwi-GUEST".to_string(),
+        building: "F3".to_string(),
+        rooms: "P6".to_string(),
+        ..Default::default()
+    }
+}
+
+fn output(response: Response) -> Vec<String> {
+    match response {
+        Response::Output(lines) => lines,
+        Response::Reboot => panic!("unexpected reboot"),
+    }
+}
+
+#[test]
+fn parses_commands() {
+    assert_eq!(
+        Command::parse("get wifi_ssid\r\n"),
+        Ok(Some(Command::Get(Key::WifiSsid)))
+    );
+    assert_eq!(
+        Command::parse("set wifi_ssid Aula Magna 2.4GHz"),
+        Ok(Some(Command::Set(Key::WifiSsid, "Aula Magna 2.4GHz".to_string())))
+    );
+    assert_eq!(
+        Command::parse("set wifi_pass"),
+        Ok(Some(Command::Set(Key::WifiPass, String::new())))
+    );
+    assert_eq!(Command::parse("  show \n"), Ok(Some(Command::Show)));
+    assert_eq!(Command::parse("\r\n"), Ok(None));
+}
+
+#[test]
+fn refuses_unknown_commands() {
+    assert_eq!(Command::parse("format"), Err("unknown command: format".to_string()));
+    assert_eq!(
+        Command::parse("get password"),
+        Err("unknown setting: password".to_string())
+    );
+    assert_eq!(Command::parse("set"), Err("unknown setting: ".to_string()));
+}
+
+#[test]
+fn defaults_until_saved() {
+    let mut store = MockStore::default();
+    let mut settings = Settings::load(&store, defaults());
+    assert_eq!(settings, defaults());
+
+    output(handle_line("set rooms P6, P7", &mut settings, &mut store));
+    // Not persisted yet
+    assert_eq!(Settings::load(&store, defaults()).rooms, "P6");
+    assert_eq!(output(handle_line("save", &mut settings, &mut store)), ["OK"]);
+    let loaded = Settings::load(&store, defaults());
+    assert_eq!(loaded.rooms, "P6, P7");
+    assert_eq!(loaded.wifi_ssid, "Wokwi-GUEST");
+}
+
+#[test]
+fn hides_secrets() {
+    let mut store = MockStore::default();
+    let mut settings = defaults();
+    output(handle_line("set wifi_pass hunter2", &mut settings, &mut store));
+    assert_eq!(settings.wifi_pass, "hunter2");
+    assert_eq!(
+        output(handle_line("get wifi_pass", &mut settings, &mut store)),
+        ["OK ********"]
+    );
+    let shown = output(handle_line("show", &mut settings, &mut store));
+    assert!(shown.contains(&"wifi_pass=********".to_string()));
+    assert!(shown.contains(&"wifi_ssid=Wokwi-GUEST".to_string()));
+    assert_eq!(shown.last().unwrap(), "OK");
+}
+
+#[test]
+fn reports_errors() {
+    let mut store = MockStore {
+        full: true,
+        ..Default::default()
+    };
+    let mut settings = defaults();
+    assert_eq!(
+        output(handle_line("save", &mut settings, &mut store)),
+        ["ERR storage: not enough space"]
+    );
+    assert_eq!(
+        output(handle_line("get foo", &mut settings, &mut store)),
+        ["ERR unknown setting: foo"]
+    );
+    let ssid = "a".repeat(33);
+    assert_eq!(
+        output(handle_line(
+            &format!("set wifi_ssid {}", ssid),
+            &mut settings,
+            &mut store
+        )),
+        ["ERR wifi_ssid is too long (at most 32 bytes)"]
+    );
+    assert_eq!(settings.wifi_ssid, "Wokwi-GUEST");
+    assert_eq!(handle_line("reboot", &mut settings, &mut store), Response::Reboot);
+}
+
+#[test]
+fn completeness() {
+    let mut settings = defaults();
+    assert!(!settings.is_complete());
+    settings.mqtt_endpoint = "mqtts://example-ats.iot.eu-west-1.amazonaws.com".to_string();
+    assert!(settings.is_complete());
+}
+
+#[test]
+fn identity_from_rooms() {
+    let mut settings = defaults();
+    assert_eq!(
+        settings.identity(),
+        Identity::Room {
+            building: "F3".to_string(),
+            room: "P6".to_string()
+        }
+    );
+    settings.rooms = " P6 , P7,".to_string();
+    assert_eq!(
+        settings.identity(),
+        Identity::Hallway {
+            building: "F3".to_string(),
+            rooms: vec!["P6".to_string(), "P7".to_string()]
+        }
+    );
+    settings.rooms = String::new();
+    assert_eq!(
+        settings.identity(),
+        Identity::Building {
+            building: "F3".to_string()
+        }
+    );
+}
+
+#[test]
+fn panel_models() {
+    let mut store = MockStore::default();
+    let mut settings = defaults();
+    assert_eq!(settings.display_model(), None);
+    assert_eq!(
+        output(handle_line("set panel epd7in5", &mut settings, &mut store)),
+        ["ERR Unknown display model: epd7in5"]
+    );
+    assert_eq!(settings.panel, "");
+    assert_eq!(
+        output(handle_line("set panel epd7in5_v2", &mut settings, &mut store)),
+        ["OK"]
+    );
+    assert_eq!(settings.display_model(), Some(DisplayModel::Epd7in5V2));
+}
+
+#[test]
+fn boards() {
+    let mut store = MockStore::default();
+    let mut settings = defaults();
+    assert_eq!(settings.board(), None);
+    assert_eq!(
+        output(handle_line("set board esp32", &mut settings, &mut store)),
+        ["ERR Unknown board: esp32"]
+    );
+    assert_eq!(
+        output(handle_line("set board devkitc", &mut settings, &mut store)),
+        ["OK"]
+    );
+    assert_eq!(settings.board(), Some(Board::DevKitC));
+}
@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use display_layout::StatusBar;
+use display_logic::status::{format_age, Status};
+
+const STALE_AFTER: Duration = Duration::from_secs(2 * 60 * 60);
+
+fn local_time(timestamp: u64) -> String {
+    format!("{:02}:{:02}", timestamp / 3600 % 24, timestamp / 60 % 60)
+}
+
+#[test]
+fn fresh_schedule() {
+    let status = Status {
+        synced_at: Some(10 * 3600 + 42 * 60),
+        rssi: Some(-67),
+        battery: Some(80),
+    };
+    assert_eq!(
+        status.bar(Some(11 * 3600), STALE_AFTER, local_time),
+        StatusBar {
+            left: "Aggiornato 10:42".to_string(),
+            right: "-67 dBm 80%".to_string(),
+            warning: false,
+        }
+    );
+}
+
+#[test]
+fn stale_schedule() {
+    let status = Status {
+        synced_at: Some(10 * 3600),
+        rssi: None,
+        battery: Some(80),
+    };
+    assert!(!status.is_stale(Some(12 * 3600 - 1), STALE_AFTER));
+    let bar = status.bar(Some(13 * 3600), STALE_AFTER, local_time);
+    assert!(bar.warning);
+    assert_eq!(bar.left, "Non aggiornato da 3 h");
+    assert_eq!(bar.right, "80%");
+}
+
+#[test]
+fn staleness_needs_a_clock() {
+    let status = Status {
+        synced_at: Some(10 * 3600),
+        ..Default::default()
+    };
+    assert!(!status.is_stale(None, STALE_AFTER));
+    assert!(!Status::default().is_stale(Some(13 * 3600), STALE_AFTER));
+    assert_eq!(
+        Status::default().bar(None, STALE_AFTER, local_time),
+        StatusBar::default()
+    );
+}
+
+#[test]
+fn ages() {
+    assert_eq!(format_age(Duration::from_secs(59 * 60)), "59 min");
+    assert_eq!(format_age(Duration::from_secs(47 * 3600)), "47 h");
+    assert_eq!(format_age(Duration::from_secs(3 * 24 * 3600)), "3 giorni");
+}
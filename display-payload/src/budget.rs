@@ -0,0 +1,81 @@
+use crate::{Encoding, Envelope, PayloadError, ONGOING_FOR};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Limits on what gets published on a topic, based on what its displays can show and hold in memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Budget {
+    /// Maximum amount of events in the payload
+    pub max_events: usize,
+    /// Only events happening today or in the following `lookahead_days` days are sent
+    pub lookahead_days: u32,
+    /// Maximum size of the encoded payload, in bytes. AWS IoT Core refuses messages bigger than 128KB, but the
+    /// displays' heap is usually the tighter limit
+    pub max_bytes: usize,
+}
+
+impl Budget {
+    /// The first timestamp outside of the look-ahead window, which ends at a local midnight
+    pub fn window_end(&self, now: u64, utc_offset: i32) -> u64 {
+        local_midnight_after(now, utc_offset) + self.lookahead_days as u64 * SECONDS_PER_DAY
+    }
+}
+
+/// The first midnight after `now` in the timezone of the displays, `utc_offset` seconds ahead of UTC at `now` (eg.
+/// 7200 for Italy in summer). Days are counted as 24 hours: past a DST change, the boundaries are off by an hour
+fn local_midnight_after(now: u64, utc_offset: i32) -> u64 {
+    let local = now as i64 + utc_offset as i64;
+    let local_midnight = local - local.rem_euclid(SECONDS_PER_DAY as i64) + SECONDS_PER_DAY as i64;
+    (local_midnight - utc_offset as i64).max(0) as u64
+}
+
+impl Envelope {
+    /// Drops the events that don't fit in the budget, counting them in `more_events`, and sets `next_change_at`.
+    /// Days start at the local midnight of the displays, `utc_offset` seconds ahead of UTC.
+    /// The size limit is checked against every encoding in `encodings`, so that every copy of the topic shows the
+    /// same events.
+    pub fn apply_budget(
+        &mut self,
+        budget: &Budget,
+        now: u64,
+        utc_offset: i32,
+        encodings: &[Encoding],
+    ) -> Result<(), PayloadError> {
+        let total = self.events.len() + self.more_events as usize;
+        let window_end = budget.window_end(now, utc_offset);
+        // Events are sorted by timestamp: the ones left out are always the last ones
+        let in_window = self
+            .events
+            .iter()
+            .take_while(|event| event.timestamp < window_end)
+            .count();
+        // The content changes when the first event is over (the lambda only publishes events until then), or at
+        // midnight if the window is hiding some events, since the window then moves forward by a day
+        let tomorrow = local_midnight_after(now, utc_offset);
+        let window_moves_at = (in_window < self.events.len()).then_some(tomorrow);
+        self.next_change_at = self
+            .events
+            .first()
+            .map(|event| event.timestamp + ONGOING_FOR.as_secs())
+            .into_iter()
+            .chain(window_moves_at)
+            .min();
+
+        self.events.truncate(in_window.min(budget.max_events));
+        self.more_events = (total - self.events.len()) as u32;
+
+        while !self.events.is_empty() && self.encoded_size(encodings)? > budget.max_bytes {
+            self.events.pop();
+            self.more_events += 1;
+        }
+        Ok(())
+    }
+
+    fn encoded_size(&self, encodings: &[Encoding]) -> Result<usize, PayloadError> {
+        let mut size = 0;
+        for encoding in encodings {
+            size = size.max(self.encode(*encoding)?.len());
+        }
+        Ok(size)
+    }
+}
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{PayloadError, SchemaVersion, SCHEMA_VERSION};
+
+/// How many bytes of a bad payload are reported, enough to recognize it without flooding the topic
+const PAYLOAD_HEAD_LEN: usize = 64;
+
+/// Published (not retained) by a display on `diagnostics/<client_id>` when it receives a payload it can't use, so
+/// that the problem shows up in the cloud instead of only in the serial logs of the board.
+/// Always encoded as JSON.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The version of the payload format the display understands
+    pub schema_version: SchemaVersion,
+    pub client_id: String,
+    /// The topic the payload has been received on
+    pub topic: String,
+    pub error: String,
+    pub payload_len: usize,
+    /// The first bytes of the payload, hex encoded
+    pub payload_head: String,
+}
+
+impl Diagnostic {
+    pub fn new(client_id: &str, topic: &str, error: &str, payload: &[u8]) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            client_id: client_id.to_string(),
+            topic: topic.to_string(),
+            error: error.to_string(),
+            payload_len: payload.len(),
+            payload_head: payload
+                .iter()
+                .take(PAYLOAD_HEAD_LEN)
+                .map(|byte| format!("{:02x}", byte))
+                .collect(),
+        }
+    }
+
+    /// The topic the diagnostics of a display are published on, eg. `diagnostics/display-240ac40001ff`
+    pub fn topic(client_id: &str) -> String {
+        format!("diagnostics/{}", client_id)
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, PayloadError> {
+        serde_json::to_vec(self).map_err(PayloadError::Json)
+    }
+}
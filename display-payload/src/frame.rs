@@ -0,0 +1,202 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Envelope, PayloadError, SchemaVersion, SCHEMA_VERSION};
+
+/// E-paper panels the framebuffers can be rendered for.
+/// New panels go at the end: the variant index is part of the postcard encoding of [`Frame`].
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisplayModel {
+    /// Waveshare 2.9" (V2), 128x296, mounted in landscape
+    #[serde(rename = "epd2in9_v2")]
+    Epd2in9V2,
+    /// Waveshare 5.83" (V2), 648x480
+    #[serde(rename = "epd5in83_v2")]
+    Epd5in83V2,
+    /// Waveshare 4.2", 400x300
+    #[serde(rename = "epd4in2")]
+    Epd4in2,
+    /// Waveshare 7.5" (V2), 800x480
+    #[serde(rename = "epd7in5_v2")]
+    Epd7in5V2,
+}
+
+impl DisplayModel {
+    pub const ALL: [DisplayModel; 4] = [
+        DisplayModel::Epd2in9V2,
+        DisplayModel::Epd5in83V2,
+        DisplayModel::Epd4in2,
+        DisplayModel::Epd7in5V2,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DisplayModel::Epd2in9V2 => "epd2in9_v2",
+            DisplayModel::Epd5in83V2 => "epd5in83_v2",
+            DisplayModel::Epd4in2 => "epd4in2",
+            DisplayModel::Epd7in5V2 => "epd7in5_v2",
+        }
+    }
+
+    /// Width and height of the panel as the driver sees it, which might be rotated compared to the screen layout
+    pub fn native_size(&self) -> (u32, u32) {
+        match self {
+            DisplayModel::Epd2in9V2 => (128, 296),
+            DisplayModel::Epd5in83V2 => (648, 480),
+            DisplayModel::Epd4in2 => (400, 300),
+            DisplayModel::Epd7in5V2 => (800, 480),
+        }
+    }
+
+    /// Size of the 1-bit framebuffer expected by the driver: rows are padded to a whole byte
+    pub fn buffer_len(&self) -> usize {
+        let (width, height) = self.native_size();
+        (width as usize).div_ceil(8) * height as usize
+    }
+
+    /// The topic the framebuffers of this model are published on, eg. `frame/epd2in9_v2/F3/P6`
+    pub fn topic(&self, topic: &str) -> String {
+        format!("frame/{}/{}", self.name(), topic)
+    }
+}
+
+impl FromStr for DisplayModel {
+    type Err = String;
+
+    /// Parses the name of a model, eg. `epd5in83_v2`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DisplayModel::ALL
+            .into_iter()
+            .find(|model| model.name() == s)
+            .ok_or_else(|| format!("Unknown display model: {}", s))
+    }
+}
+
+/// A framebuffer rendered server-side, ready to be sent as is to the panel.
+/// Always encoded with postcard.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub schema_version: SchemaVersion,
+    pub generated_at: u64,
+    pub next_change_at: Option<u64>,
+    pub model: DisplayModel,
+    /// The framebuffer of the first page in the native layout of the panel (1 bit per pixel, white is 1),
+    /// compressed with [`compress`]
+    pub data: Vec<u8>,
+    /// The framebuffers of the following pages, if the events don't fit on a single page
+    pub next_pages: Vec<Vec<u8>>,
+}
+
+impl Frame {
+    /// `pages` are the uncompressed framebuffers of every page, there must be at least one.
+    pub fn new(envelope: &Envelope, model: DisplayModel, pages: &[&[u8]]) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            generated_at: envelope.generated_at,
+            next_change_at: envelope.next_change_at,
+            model,
+            data: compress(pages[0]),
+            next_pages: pages[1..].iter().map(|page| compress(page)).collect(),
+        }
+    }
+
+    pub fn page_count(&self) -> usize {
+        1 + self.next_pages.len()
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, PayloadError> {
+        postcard::to_allocvec(self).map_err(PayloadError::Postcard)
+    }
+
+    /// Parses a frame, refusing it if it was written with an incompatible major version.
+    pub fn decode(payload: &[u8]) -> Result<Self, PayloadError> {
+        #[derive(Deserialize)]
+        struct Header {
+            schema_version: SchemaVersion,
+        }
+        let header: Header = postcard::from_bytes(payload).map_err(PayloadError::Postcard)?;
+        if !SCHEMA_VERSION.is_compatible_with(&header.schema_version) {
+            return Err(PayloadError::UnsupportedVersion(header.schema_version));
+        }
+        postcard::from_bytes(payload).map_err(PayloadError::Postcard)
+    }
+
+    /// Decompresses the framebuffer of a page, checking that it has been rendered for `model`.
+    /// Pages past the last one return the last page.
+    pub fn buffer(&self, model: DisplayModel, page: usize) -> Result<Vec<u8>, PayloadError> {
+        if self.model != model {
+            return Err(PayloadError::InvalidFrame(
+                "rendered for a different display model",
+            ));
+        }
+        let data = match page.min(self.next_pages.len()) {
+            0 => &self.data,
+            page => &self.next_pages[page - 1],
+        };
+        let buffer = decompress(data)?;
+        if buffer.len() != model.buffer_len() {
+            return Err(PayloadError::InvalidFrame("wrong framebuffer size"));
+        }
+        Ok(buffer)
+    }
+}
+
+/// Compresses a framebuffer with PackBits, a run-length encoding: e-paper screens are mostly blank, and the decoder
+/// is small enough for any board.
+/// Each chunk starts with a header byte `n`: if `n < 128`, the next `n + 1` bytes are copied as is, otherwise the
+/// next byte is repeated `257 - n` times.
+pub fn compress(buffer: &[u8]) -> Vec<u8> {
+    let mut compressed = Vec::new();
+    let mut literals_start = 0;
+    let mut i = 0;
+    while i < buffer.len() {
+        let run = buffer[i..]
+            .iter()
+            .take(128)
+            .take_while(|byte| **byte == buffer[i])
+            .count();
+        // A run of two is only worth it when it doesn't interrupt a literal chunk
+        if run > 2 || (run == 2 && literals_start == i) {
+            push_literals(&mut compressed, &buffer[literals_start..i]);
+            compressed.push((257 - run) as u8);
+            compressed.push(buffer[i]);
+            i += run;
+            literals_start = i;
+        } else {
+            i += 1;
+        }
+    }
+    push_literals(&mut compressed, &buffer[literals_start..]);
+    compressed
+}
+
+fn push_literals(compressed: &mut Vec<u8>, literals: &[u8]) {
+    for chunk in literals.chunks(128) {
+        compressed.push((chunk.len() - 1) as u8);
+        compressed.extend_from_slice(chunk);
+    }
+}
+
+pub fn decompress(compressed: &[u8]) -> Result<Vec<u8>, PayloadError> {
+    let mut buffer = Vec::new();
+    let mut i = 0;
+    while i < compressed.len() {
+        let header = compressed[i] as usize;
+        i += 1;
+        if header < 128 {
+            let literals = compressed
+                .get(i..i + header + 1)
+                .ok_or(PayloadError::InvalidFrame("truncated literal chunk"))?;
+            buffer.extend_from_slice(literals);
+            i += header + 1;
+        } else if header > 128 {
+            let byte = *compressed
+                .get(i)
+                .ok_or(PayloadError::InvalidFrame("truncated run chunk"))?;
+            buffer.resize(buffer.len() + 257 - header, byte);
+            i += 1;
+        }
+    }
+    Ok(buffer)
+}
@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{PayloadError, SchemaVersion};
+
+/// Published (not retained) by a display on `telemetry/<client_id>` at regular intervals, so that a display that
+/// stopped working (no power, no network, stuck firmware) is noticed.
+/// Always encoded as JSON.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Heartbeat {
+    /// The version of the payload format the display understands
+    pub schema_version: SchemaVersion,
+    pub client_id: String,
+    pub firmware_version: String,
+    /// Battery level (%), if the board measures it
+    pub battery: Option<u8>,
+    /// Signal strength of the access point (dBm)
+    pub rssi: Option<i8>,
+    /// Seconds since the board started (woke up, in deep sleep mode)
+    pub uptime: u64,
+    /// [`payload_hash`] of the last payload shown, hex encoded
+    pub last_payload_hash: Option<String>,
+    /// In how many seconds the next heartbeat is due
+    pub interval: u64,
+}
+
+impl Heartbeat {
+    /// The topic the heartbeats of a display are published on, eg. `telemetry/display-240ac40001ff`
+    pub fn topic(client_id: &str) -> String {
+        format!("telemetry/{}", client_id)
+    }
+
+    /// How many heartbeats have been missed at `now`, if this one has been received at `received_at`
+    pub fn missed(&self, received_at: u64, now: u64) -> u64 {
+        now.saturating_sub(received_at) / self.interval.max(1)
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, PayloadError> {
+        serde_json::to_vec(self).map_err(PayloadError::Json)
+    }
+
+    pub fn decode(payload: &[u8]) -> Result<Self, PayloadError> {
+        serde_json::from_slice(payload).map_err(PayloadError::Json)
+    }
+}
+
+/// A short fingerprint of a payload (64-bit FNV-1a, hex encoded), to tell which one a display is showing. Not meant to
+/// resist tampering
+pub fn payload_hash(payload: &[u8]) -> String {
+    let hash = payload.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
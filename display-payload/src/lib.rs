@@ -0,0 +1,257 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+mod budget;
+mod diagnostic;
+mod frame;
+mod heartbeat;
+mod release;
+
+pub use budget::Budget;
+pub use diagnostic::Diagnostic;
+pub use frame::{compress, decompress, DisplayModel, Frame};
+pub use heartbeat::{payload_hash, Heartbeat};
+pub use release::Release;
+
+/// Version of the payload format produced by this crate.
+/// Bump the minor version for backwards compatible changes (eg. a new optional field), and the major version
+/// when older displays would misread the payload: they will refuse it instead of showing garbage.
+pub const SCHEMA_VERSION: SchemaVersion = SchemaVersion { major: 1, minor: 4 };
+
+/// How long an event lasts after its start, as the payloads don't tell when events end: the lambda keeps publishing
+/// it until then, and the displays show it as ongoing.
+pub const ONGOING_FOR: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SchemaVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl SchemaVersion {
+    /// Whether a consumer built against `self` can read a payload written with `other`.
+    /// Unknown fields are ignored when deserializing, so newer minor versions are fine.
+    pub fn is_compatible_with(&self, other: &SchemaVersion) -> bool {
+        self.major == other.major
+    }
+}
+
+impl fmt::Display for SchemaVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+impl FromStr for SchemaVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (major, minor) = s
+            .split_once('.')
+            .ok_or_else(|| format!("Invalid schema version: {}", s))?;
+        Ok(Self {
+            major: major
+                .parse()
+                .map_err(|_| format!("Invalid schema major version: {}", s))?,
+            minor: minor
+                .parse()
+                .map_err(|_| format!("Invalid schema minor version: {}", s))?,
+        })
+    }
+}
+
+// Serialized as "major.minor" so that the version is readable when inspecting the MQTT topics
+impl Serialize for SchemaVersion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for SchemaVersion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let version = String::deserialize(deserializer)?;
+        version.parse().map_err(de::Error::custom)
+    }
+}
+
+/// Which kind of topic the payload has been published on: a building topic (eg. `F3`) carries the events of all
+/// its rooms, while a room topic (eg. `F3/P6`) only carries the events of that room.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TopicKind {
+    Building,
+    Room,
+}
+
+/// A building or a room, identified by the id used in the MQTT topics and with a human readable name for the screen.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Place {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SEvent {
+    pub id: String,
+    pub title: String,
+    pub timestamp: u64,
+    pub datetime: String,
+    pub building: String,
+    pub room: String,
+}
+
+/// How a payload is serialized on the wire.
+/// JSON is published on the plain topics (eg. `F3/P6`), while every other encoding gets its own copy of the topics
+/// under a prefix (eg. `postcard/F3/P6`), so that each class of displays can subscribe to the format it parses best.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Json,
+    /// Compact binary format, cheaper to parse on low-power displays.
+    /// Not self-describing: a minor version may only append new fields at the end of [`Envelope`].
+    Postcard,
+}
+
+impl Encoding {
+    pub fn topic(&self, topic: &str) -> String {
+        match self {
+            Encoding::Json => topic.to_string(),
+            Encoding::Postcard => format!("postcard/{}", topic),
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Encoding::Json => "application/json",
+            Encoding::Postcard => "application/octet-stream",
+        }
+    }
+}
+
+/// The message published (retained) on every display topic.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Envelope {
+    pub schema_version: SchemaVersion,
+    /// Unix timestamp (seconds) of when the payload was generated
+    pub generated_at: u64,
+    pub topic_kind: TopicKind,
+    pub building: Place,
+    /// Only set for room topics
+    pub room: Option<Place>,
+    /// Sorted by timestamp
+    pub events: Vec<SEvent>,
+    /// How many events have been left out of `events` to fit the topic budget, so that the display can show an
+    /// "and N more" line
+    #[serde(default)]
+    pub more_events: u32,
+    /// Unix timestamp (seconds) of the next moment the content of this topic will change, if known.
+    /// Displays can sleep until then instead of polling
+    #[serde(default)]
+    pub next_change_at: Option<u64>,
+    /// Names of the rooms of the events, shown in the room headers of building screens. A room missing from this
+    /// list is shown by its id
+    #[serde(default)]
+    pub room_names: Vec<Place>,
+}
+
+impl Envelope {
+    pub fn new(
+        generated_at: u64,
+        topic_kind: TopicKind,
+        building: Place,
+        room: Option<Place>,
+        events: Vec<SEvent>,
+    ) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            generated_at,
+            topic_kind,
+            building,
+            room,
+            events,
+            more_events: 0,
+            next_change_at: None,
+            room_names: Vec::new(),
+        }
+    }
+
+    /// The name of the room of an event, from `room_names`, or its id
+    pub fn room_name<'a>(&'a self, room: &'a str) -> &'a str {
+        self.room_names
+            .iter()
+            .find(|place| place.id == room)
+            .map_or(room, |place| place.name.as_str())
+    }
+
+    /// How long the content stays unchanged after `now`, capped to `max` so that displays still check in
+    /// periodically (eg. to catch newly created events).
+    pub fn valid_for(&self, now: u64, max: Duration) -> Duration {
+        match self.next_change_at {
+            Some(next_change_at) => {
+                Duration::from_secs(next_change_at.saturating_sub(now)).min(max)
+            }
+            None => max,
+        }
+    }
+
+    pub fn encode(&self, encoding: Encoding) -> Result<Vec<u8>, PayloadError> {
+        match encoding {
+            Encoding::Json => serde_json::to_vec(self).map_err(PayloadError::Json),
+            Encoding::Postcard => postcard::to_allocvec(self).map_err(PayloadError::Postcard),
+        }
+    }
+
+    /// Parses a payload, refusing it if it was written with an incompatible major version.
+    pub fn decode(payload: &[u8], encoding: Encoding) -> Result<Self, PayloadError> {
+        // Only the version is parsed first: a payload with a different major version might not even match our struct
+        #[derive(Deserialize)]
+        struct Header {
+            schema_version: SchemaVersion,
+        }
+        let header: Header = match encoding {
+            Encoding::Json => serde_json::from_slice(payload).map_err(PayloadError::Json)?,
+            // schema_version is the first field, the rest of the payload is simply left unread
+            Encoding::Postcard => postcard::from_bytes(payload).map_err(PayloadError::Postcard)?,
+        };
+        if !SCHEMA_VERSION.is_compatible_with(&header.schema_version) {
+            return Err(PayloadError::UnsupportedVersion(header.schema_version));
+        }
+        match encoding {
+            Encoding::Json => serde_json::from_slice(payload).map_err(PayloadError::Json),
+            Encoding::Postcard => postcard::from_bytes(payload).map_err(PayloadError::Postcard),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PayloadError {
+    UnsupportedVersion(SchemaVersion),
+    Json(serde_json::Error),
+    Postcard(postcard::Error),
+    InvalidFrame(&'static str),
+}
+
+impl fmt::Display for PayloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PayloadError::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported payload schema version {} (supported: {}.x)",
+                version, SCHEMA_VERSION.major
+            ),
+            PayloadError::Json(e) => write!(f, "Malformed JSON payload: {}", e),
+            PayloadError::Postcard(e) => write!(f, "Malformed postcard payload: {}", e),
+            PayloadError::InvalidFrame(reason) => write!(f, "Invalid frame: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for PayloadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PayloadError::UnsupportedVersion(_) | PayloadError::InvalidFrame(_) => None,
+            PayloadError::Json(e) => Some(e),
+            PayloadError::Postcard(e) => Some(e),
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+
+use crate::PayloadError;
+
+/// Announces the firmware the displays of a variant should run, published (retained) on `control/firmware/<variant>`.
+/// Displays running an older version download the image and flash it over the air, after checking it against the
+/// hash and the signature.
+/// Always encoded as JSON.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Release {
+    /// Semantic version of the firmware, eg. `0.2.0`
+    pub version: String,
+    /// Where to download the image from (HTTPS, or plain HTTP for a local test server)
+    pub url: String,
+    /// SHA-256 of the image, hex encoded
+    pub sha256: String,
+    /// Ed25519 signature of [`Release::signed_message`], hex encoded
+    pub signature: String,
+}
+
+impl Release {
+    /// The control topic of a firmware variant, eg. `control/firmware/epd5in83_v2`
+    pub fn topic(variant: &str) -> String {
+        format!("control/firmware/{}", variant)
+    }
+
+    /// What the signature covers: the variant, the version and the SHA-256 (lowercase hex) on separate lines. Not only
+    /// the hash: an old image can't be announced as a newer version, nor the image of a variant on another one
+    pub fn signed_message(&self, variant: &str) -> Vec<u8> {
+        format!("{}\n{}\n{}", variant, self.version, self.sha256.to_lowercase()).into_bytes()
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, PayloadError> {
+        serde_json::to_vec(self).map_err(PayloadError::Json)
+    }
+
+    pub fn decode(payload: &[u8]) -> Result<Self, PayloadError> {
+        serde_json::from_slice(payload).map_err(PayloadError::Json)
+    }
+}
@@ -0,0 +1,182 @@
+use std::time::Duration;
+
+use display_payload::{Budget, Encoding, Envelope, Place, SEvent, TopicKind, ONGOING_FOR};
+
+// 2023-07-07 10:00 UTC
+const NOW: u64 = 1688724000;
+const HOUR: u64 = 60 * 60;
+// Italy in summer
+const CEST: i32 = 2 * 60 * 60;
+
+fn envelope_at(timestamps: &[u64]) -> Envelope {
+    let events = timestamps
+        .iter()
+        .enumerate()
+        .map(|(i, timestamp)| SEvent {
+            id: i.to_string(),
+            title: format!("Evento {}", i),
+            timestamp: *timestamp,
+            datetime: String::new(),
+            building: "F3".to_string(),
+            room: "P6".to_string(),
+        })
+        .collect();
+    Envelope::new(
+        NOW,
+        TopicKind::Building,
+        Place {
+            id: "F3".to_string(),
+            name: "F3".to_string(),
+        },
+        None,
+        events,
+    )
+}
+
+const UNLIMITED: Budget = Budget {
+    max_events: usize::MAX,
+    lookahead_days: 365,
+    max_bytes: usize::MAX,
+};
+
+#[test]
+fn limits_event_count() {
+    let mut envelope = envelope_at(&[NOW + HOUR, NOW + 2 * HOUR, NOW + 3 * HOUR]);
+    let budget = Budget {
+        max_events: 2,
+        ..UNLIMITED
+    };
+    envelope
+        .apply_budget(&budget, NOW, 0, &[Encoding::Json])
+        .unwrap();
+    assert_eq!(envelope.events.len(), 2);
+    assert_eq!(envelope.more_events, 1);
+}
+
+#[test]
+fn limits_lookahead_window() {
+    // Today only: the event at 23:00 is kept, the one after midnight isn't
+    let mut envelope = envelope_at(&[NOW + 13 * HOUR, NOW + 15 * HOUR, NOW + 40 * HOUR]);
+    let budget = Budget {
+        lookahead_days: 0,
+        ..UNLIMITED
+    };
+    envelope
+        .apply_budget(&budget, NOW, 0, &[Encoding::Json])
+        .unwrap();
+    assert_eq!(envelope.events.len(), 1);
+    assert_eq!(envelope.more_events, 2);
+
+    let mut envelope = envelope_at(&[NOW + 13 * HOUR, NOW + 15 * HOUR, NOW + 40 * HOUR]);
+    let budget = Budget {
+        lookahead_days: 1,
+        ..UNLIMITED
+    };
+    envelope
+        .apply_budget(&budget, NOW, 0, &[Encoding::Json])
+        .unwrap();
+    assert_eq!(envelope.events.len(), 2);
+    assert_eq!(envelope.more_events, 1);
+}
+
+#[test]
+fn limits_encoded_size_for_every_encoding() {
+    let timestamps: Vec<u64> = (1..=10).map(|i| NOW + i * 60).collect();
+    let mut envelope = envelope_at(&timestamps);
+    let budget = Budget {
+        max_bytes: 512,
+        ..UNLIMITED
+    };
+    envelope
+        .apply_budget(&budget, NOW, 0, &[Encoding::Postcard, Encoding::Json])
+        .unwrap();
+    assert!(envelope.encode(Encoding::Json).unwrap().len() <= 512);
+    assert!(!envelope.events.is_empty());
+    assert_eq!(envelope.events.len() + envelope.more_events as usize, 10);
+}
+
+#[test]
+fn untouched_when_within_budget() {
+    let mut envelope = envelope_at(&[NOW + HOUR, NOW + 2 * HOUR]);
+    let expected = envelope.clone();
+    envelope
+        .apply_budget(&UNLIMITED, NOW, 0, &[Encoding::Json])
+        .unwrap();
+    assert_eq!(envelope.events, expected.events);
+    assert_eq!(envelope.more_events, 0);
+}
+
+#[test]
+fn next_change_at_first_event_end() {
+    let mut envelope = envelope_at(&[NOW + HOUR, NOW + 2 * HOUR]);
+    envelope
+        .apply_budget(&UNLIMITED, NOW, 0, &[Encoding::Json])
+        .unwrap();
+    // Shown as ongoing until then, like the displays do
+    assert_eq!(envelope.next_change_at, Some(NOW + HOUR + ONGOING_FOR.as_secs()));
+    assert_eq!(
+        envelope.valid_for(NOW, Duration::from_secs(24 * HOUR)),
+        Duration::from_secs(2 * HOUR)
+    );
+    assert_eq!(
+        envelope.valid_for(NOW, Duration::from_secs(60)),
+        Duration::from_secs(60)
+    );
+}
+
+#[test]
+fn next_change_at_window_move() {
+    // The event in two days enters the window at midnight, before the first event of the day after starts
+    let mut envelope = envelope_at(&[NOW + 40 * HOUR]);
+    let budget = Budget {
+        lookahead_days: 0,
+        ..UNLIMITED
+    };
+    envelope
+        .apply_budget(&budget, NOW, 0, &[Encoding::Json])
+        .unwrap();
+    assert!(envelope.events.is_empty());
+    assert_eq!(envelope.next_change_at, Some(NOW + 14 * HOUR));
+}
+
+#[test]
+fn no_next_change_without_events() {
+    let mut envelope = envelope_at(&[]);
+    envelope
+        .apply_budget(&UNLIMITED, NOW, 0, &[Encoding::Json])
+        .unwrap();
+    assert_eq!(envelope.next_change_at, None);
+    assert_eq!(
+        envelope.valid_for(NOW, Duration::from_secs(60)),
+        Duration::from_secs(60)
+    );
+}
+
+#[test]
+fn days_start_at_local_midnight() {
+    // 2023-07-08 01:30 in Italy, still 2023-07-07 in UTC
+    let now = NOW + 13 * HOUR + 30 * 60;
+    // 2023-07-08 at 10:00 and 23:00, 2023-07-09 at 10:00 in Italy
+    let timestamps = [NOW + 22 * HOUR, NOW + 35 * HOUR, NOW + 46 * HOUR];
+    let budget = Budget {
+        lookahead_days: 0,
+        ..UNLIMITED
+    };
+    assert_eq!(budget.window_end(now, CEST), NOW + 36 * HOUR);
+
+    let mut envelope = envelope_at(&timestamps);
+    envelope
+        .apply_budget(&budget, now, CEST, &[Encoding::Json])
+        .unwrap();
+    assert_eq!(envelope.events.len(), 2);
+    assert_eq!(envelope.more_events, 1);
+    // The first event is over before the window moves
+    assert_eq!(envelope.next_change_at, Some(NOW + 23 * HOUR));
+
+    // The window moves at the next local midnight, not at the UTC one in half an hour
+    let mut envelope = envelope_at(&timestamps[2..]);
+    envelope
+        .apply_budget(&budget, now, CEST, &[Encoding::Json])
+        .unwrap();
+    assert_eq!(envelope.next_change_at, Some(NOW + 36 * HOUR));
+}
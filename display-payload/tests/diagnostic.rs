@@ -0,0 +1,18 @@
+use display_payload::{Diagnostic, SCHEMA_VERSION};
+
+#[test]
+fn reports_the_head_of_the_payload() {
+    let payload = vec![0xab; 200];
+    let diagnostic = Diagnostic::new("display-240ac40001ff", "postcard/F3/P6", "Malformed payload", &payload);
+    assert_eq!(diagnostic.schema_version, SCHEMA_VERSION);
+    assert_eq!(diagnostic.payload_len, 200);
+    assert_eq!(diagnostic.payload_head, "ab".repeat(64));
+}
+
+#[test]
+fn encodes_as_json() {
+    let diagnostic = Diagnostic::new("display-240ac40001ff", "F3/P6", "Malformed JSON payload", b"{\"sche");
+    let encoded = diagnostic.encode().unwrap();
+    assert_eq!(serde_json::from_slice::<Diagnostic>(&encoded).unwrap(), diagnostic);
+    assert_eq!(Diagnostic::topic("display-240ac40001ff"), "diagnostics/display-240ac40001ff");
+}
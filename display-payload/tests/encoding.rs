@@ -0,0 +1,117 @@
+use std::time::{Duration, Instant};
+
+use display_payload::{Encoding, Envelope, PayloadError, Place, SEvent, TopicKind, SCHEMA_VERSION};
+
+fn sample_envelope(events: usize) -> Envelope {
+    let events = (0..events)
+        .map(|i| SEvent {
+            id: format!("53dc4d37-cffa-4f76-80c9-8b7d4a4d{:04x}", i),
+            title: format!("Riunione di dipartimento n. {}", i),
+            timestamp: 1688690076 + i as u64 * 3600,
+            datetime: format!("2023-07-07 {:02}:00", i % 24),
+            building: "F3".to_string(),
+            room: "P6".to_string(),
+        })
+        .collect();
+    Envelope::new(
+        1688690000,
+        TopicKind::Room,
+        Place {
+            id: "F3".to_string(),
+            name: "Edificio F3".to_string(),
+        },
+        Some(Place {
+            id: "P6".to_string(),
+            name: "Aula P6".to_string(),
+        }),
+        events,
+    )
+}
+
+fn time_decode(payload: &[u8], encoding: Encoding, iterations: u32) -> Duration {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        let envelope = Envelope::decode(payload, encoding).unwrap();
+        std::hint::black_box(envelope);
+    }
+    start.elapsed() / iterations
+}
+
+#[test]
+fn roundtrip() {
+    let envelope = sample_envelope(8);
+    for encoding in [Encoding::Json, Encoding::Postcard] {
+        let payload = envelope.encode(encoding).unwrap();
+        assert_eq!(Envelope::decode(&payload, encoding).unwrap(), envelope);
+    }
+}
+
+#[test]
+fn postcard_is_smaller_than_json() {
+    let envelope = sample_envelope(20);
+    let json = envelope.encode(Encoding::Json).unwrap();
+    let postcard = envelope.encode(Encoding::Postcard).unwrap();
+
+    // Decoding times are only printed (`cargo test -- --nocapture`): they depend on the machine and its load
+    let json_time = time_decode(&json, Encoding::Json, 200);
+    let postcard_time = time_decode(&postcard, Encoding::Postcard, 200);
+    println!("JSON: {} bytes, decoded in {:?}", json.len(), json_time);
+    println!(
+        "Postcard: {} bytes, decoded in {:?}",
+        postcard.len(),
+        postcard_time
+    );
+
+    // Mostly field names and quoting that postcard doesn't need
+    assert!(postcard.len() * 3 < json.len() * 2);
+}
+
+#[test]
+fn refuses_unknown_major_version() {
+    let mut envelope = sample_envelope(1);
+    envelope.schema_version.major = SCHEMA_VERSION.major + 1;
+    for encoding in [Encoding::Json, Encoding::Postcard] {
+        let payload = envelope.encode(encoding).unwrap();
+        assert!(matches!(
+            Envelope::decode(&payload, encoding),
+            Err(PayloadError::UnsupportedVersion(version)) if version == envelope.schema_version
+        ));
+    }
+}
+
+#[test]
+fn accepts_newer_minor_version_with_appended_fields() {
+    let mut envelope = sample_envelope(2);
+    envelope.schema_version.minor = SCHEMA_VERSION.minor + 1;
+
+    let mut json: serde_json::Value =
+        serde_json::from_slice(&envelope.encode(Encoding::Json).unwrap()).unwrap();
+    json["field_from_the_future"] = serde_json::json!(42);
+    let json = serde_json::to_vec(&json).unwrap();
+    assert_eq!(Envelope::decode(&json, Encoding::Json).unwrap(), envelope);
+
+    let mut postcard = envelope.encode(Encoding::Postcard).unwrap();
+    postcard.push(42);
+    assert_eq!(
+        Envelope::decode(&postcard, Encoding::Postcard).unwrap(),
+        envelope
+    );
+}
+
+#[test]
+fn room_names_of_building_topics() {
+    let mut envelope = sample_envelope(2);
+    envelope.topic_kind = TopicKind::Building;
+    envelope.room = None;
+    envelope.room_names = vec![Place {
+        id: "P6".to_string(),
+        name: "Aula P6".to_string(),
+    }];
+    for encoding in [Encoding::Json, Encoding::Postcard] {
+        let payload = envelope.encode(encoding).unwrap();
+        assert_eq!(Envelope::decode(&payload, encoding).unwrap(), envelope);
+    }
+    assert_eq!(envelope.room_name("P6"), "Aula P6");
+    // Unnamed rooms are shown by their id
+    assert_eq!(envelope.room_name("P7"), "P7");
+}
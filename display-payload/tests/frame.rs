@@ -0,0 +1,93 @@
+use display_payload::{
+    compress, decompress, DisplayModel, Envelope, Frame, PayloadError, Place, TopicKind,
+};
+
+fn blank_buffer(model: DisplayModel) -> Vec<u8> {
+    vec![0xFF; model.buffer_len()]
+}
+
+#[test]
+fn compress_roundtrip() {
+    let mut buffer = blank_buffer(DisplayModel::Epd2in9V2);
+    // Some text-like noise in the middle of the screen
+    for (i, byte) in buffer.iter_mut().enumerate().skip(1000).take(600) {
+        *byte = (i * 37 % 251) as u8;
+    }
+    let compressed = compress(&buffer);
+    assert!(compressed.len() < buffer.len() / 2);
+    assert_eq!(decompress(&compressed).unwrap(), buffer);
+
+    for edge_case in [
+        vec![],
+        vec![1],
+        vec![1, 1],
+        vec![1, 2, 2, 3],
+        vec![7; 300],
+        (0..=255).collect(),
+    ] {
+        assert_eq!(decompress(&compress(&edge_case)).unwrap(), edge_case);
+    }
+}
+
+#[test]
+fn blank_screen_is_tiny() {
+    let buffer = blank_buffer(DisplayModel::Epd5in83V2);
+    assert!(compress(&buffer).len() < 1024);
+}
+
+#[test]
+fn truncated_data_is_refused() {
+    assert!(matches!(
+        decompress(&[5, 1, 2]),
+        Err(PayloadError::InvalidFrame(_))
+    ));
+    assert!(matches!(
+        decompress(&[200]),
+        Err(PayloadError::InvalidFrame(_))
+    ));
+}
+
+#[test]
+fn frame_roundtrip() {
+    let envelope = Envelope::new(
+        1688690000,
+        TopicKind::Building,
+        Place {
+            id: "F3".to_string(),
+            name: "F3".to_string(),
+        },
+        None,
+        vec![],
+    );
+    let blank = blank_buffer(DisplayModel::Epd2in9V2);
+    let mut second_page = blank.clone();
+    second_page[42] = 0;
+    let frame = Frame::new(&envelope, DisplayModel::Epd2in9V2, &[&blank, &second_page]);
+    let decoded = Frame::decode(&frame.encode().unwrap()).unwrap();
+    assert_eq!(decoded, frame);
+    assert_eq!(decoded.page_count(), 2);
+    assert_eq!(decoded.buffer(DisplayModel::Epd2in9V2, 0).unwrap(), blank);
+    assert_eq!(
+        decoded.buffer(DisplayModel::Epd2in9V2, 1).unwrap(),
+        second_page
+    );
+    assert_eq!(
+        decoded.buffer(DisplayModel::Epd2in9V2, 2).unwrap(),
+        second_page
+    );
+    assert!(decoded.buffer(DisplayModel::Epd5in83V2, 0).is_err());
+}
+
+#[test]
+fn model_names() {
+    for model in DisplayModel::ALL {
+        assert_eq!(model.name().parse(), Ok(model));
+    }
+    assert_eq!(
+        "epd7in5".parse::<DisplayModel>(),
+        Err("Unknown display model: epd7in5".to_string())
+    );
+    // The native rows of the 4.2" and 7.5" panels are whole bytes
+    assert_eq!(DisplayModel::Epd4in2.buffer_len(), 50 * 300);
+    assert_eq!(DisplayModel::Epd7in5V2.buffer_len(), 100 * 480);
+}
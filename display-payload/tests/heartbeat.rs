@@ -0,0 +1,38 @@
+use display_payload::{payload_hash, Heartbeat, SCHEMA_VERSION};
+
+fn heartbeat() -> Heartbeat {
+    Heartbeat {
+        schema_version: SCHEMA_VERSION,
+        client_id: "display-240ac40001ff".to_string(),
+        firmware_version: "0.2.0".to_string(),
+        battery: Some(80),
+        rssi: Some(-67),
+        uptime: 45,
+        last_payload_hash: Some(payload_hash(b"{}")),
+        interval: 15 * 60,
+    }
+}
+
+#[test]
+fn round_trip() {
+    let heartbeat = heartbeat();
+    assert_eq!(Heartbeat::decode(&heartbeat.encode().unwrap()).unwrap(), heartbeat);
+    assert_eq!(Heartbeat::topic("display-240ac40001ff"), "telemetry/display-240ac40001ff");
+}
+
+#[test]
+fn missed_heartbeats() {
+    let heartbeat = heartbeat();
+    assert_eq!(heartbeat.missed(1000, 1000 + 15 * 60 - 1), 0);
+    assert_eq!(heartbeat.missed(1000, 1000 + 45 * 60), 3);
+    // Clocks aren't perfectly in sync
+    assert_eq!(heartbeat.missed(1000, 900), 0);
+}
+
+#[test]
+fn payload_hashes() {
+    // Reference values of 64-bit FNV-1a
+    assert_eq!(payload_hash(b""), "cbf29ce484222325");
+    assert_eq!(payload_hash(b"a"), "af63dc4c8601ec8c");
+    assert_ne!(payload_hash(b"{\"events\":[]}"), payload_hash(b"{\"events\":[ ]}"));
+}
@@ -0,0 +1,27 @@
+use display_payload::Release;
+
+#[test]
+fn round_trip() {
+    let release = Release {
+        version: "0.2.0".to_string(),
+        url: "http://192.168.1.10:8000/rmqtt-0.2.0.bin".to_string(),
+        sha256: "00".repeat(32),
+        signature: "00".repeat(64),
+    };
+    assert_eq!(Release::decode(&release.encode().unwrap()).unwrap(), release);
+    assert_eq!(Release::topic("epd5in83_v2"), "control/firmware/epd5in83_v2");
+    assert!(Release::decode(b"{\"version\": \"0.2.0\"}").is_err());
+}
+
+#[test]
+fn signed_message() {
+    // Must match scripts/sign_firmware.py
+    let release = Release {
+        version: "0.2.0".to_string(),
+        url: "http://192.168.1.10:8000/rmqtt-0.2.0.bin".to_string(),
+        sha256: "AB".repeat(32),
+        signature: "00".repeat(64),
+    };
+    let expected = format!("display\n0.2.0\n{}", "ab".repeat(32));
+    assert_eq!(release.signed_message("display"), expected.into_bytes());
+}
@@ -2,9 +2,13 @@ use std::{collections::HashMap, time::{SystemTime, UNIX_EPOCH}, fs::File, io::Re
 
 use aws_lambda_events::event::cloudwatch_events::CloudWatchEvent;
 use aws_sdk_dynamodb::types::AttributeValue;
+use chrono::{Offset, TimeZone};
+use chrono_tz::Tz;
+use display_payload::{Budget, DisplayModel, Encoding, Envelope, Frame, Place, SEvent, TopicKind, ONGOING_FOR};
 use lambda_runtime::{run, service_fn, Error, LambdaEvent};
 use reqwest::{Certificate, Identity};
 use serde::{Serialize, Deserialize};
+use tracing::error;
 use tracing::log::info;
 use serde_json::json;
 use url::form_urlencoded::byte_serialize;
@@ -18,6 +22,25 @@ const FIELD_DATETIME: &str = "datetime";
 const FIELD_TITLE: &str = "title";
 const FIELD_BUILDING: &str = "building";
 const FIELD_ROOM: &str = "room";
+// Human readable names shown in the header of the displays. Buildings and rooms missing from these lists fall back to their id.
+const BUILDING_NAMES: &[(&str, &str)] = &[("F3", "Edificio F3")];
+const ROOM_NAMES: &[((&str, &str), &str)] = &[(("F3", "P6"), "Aula P6")];
+// Every topic is published once per encoding: keep here only the encodings used by the deployed device classes.
+// JSON goes on the plain topics, the others on prefixed topics (eg. postcard/F3/P6)
+const PUBLISHED_ENCODINGS: &[Encoding] = &[Encoding::Json, Encoding::Postcard];
+// Display models that get their framebuffers rendered here and published on frame/<model>/<topic>, for the boards
+// built with the server_render feature
+const RENDERED_MODELS: &[DisplayModel] = &DisplayModel::ALL;
+// Display profiles: what fits on each panel, and in the heap of the board driving it
+// 2.9" (296x128): 3 pages of 7 rows (the last row of the screen is kept for the footer)
+const BUDGET_EPD2IN9: Budget = Budget { max_events: 21, lookahead_days: 1, max_bytes: 8 * 1024 };
+// 5.83" (648x480): 2 pages of 22 rows (the last row of the screen is kept for the footer)
+const BUDGET_EPD5IN83: Budget = Budget { max_events: 44, lookahead_days: 7, max_bytes: 16 * 1024 };
+// The profile of the displays subscribed to each topic. Topics missing from this list use DEFAULT_BUDGET
+const TOPIC_BUDGETS: &[(&str, Budget)] = &[("F3/P6", BUDGET_EPD2IN9)];
+const DEFAULT_BUDGET: Budget = BUDGET_EPD5IN83;
+// Timezone of the displays (the TIMEZONE of the firmware): the look-ahead window of the budgets ends at their midnight
+const TIMEZONE: Tz = chrono_tz::Europe::Rome;
 
 #[derive(Serialize, Deserialize)]
 pub struct Event {
@@ -42,6 +65,44 @@ impl Event {
     }
 }
 
+impl From<&Event> for SEvent {
+    fn from(event: &Event) -> Self {
+        Self {
+            id: event.id.clone(),
+            title: event.title.clone(),
+            timestamp: event.timestamp,
+            datetime: event.datetime.clone(),
+            building: event.building.clone(),
+            room: event.room.clone(),
+        }
+    }
+}
+
+fn building_place(building: &str) -> Place {
+    let name = BUILDING_NAMES.iter()
+        .find(|(id, _)| *id == building)
+        .map_or(building, |(_, name)| name);
+    Place { id: building.to_string(), name: name.to_string() }
+}
+
+fn topic_budget(topic: &str) -> &'static Budget {
+    TOPIC_BUDGETS.iter()
+        .find(|(budget_topic, _)| *budget_topic == topic)
+        .map_or(&DEFAULT_BUDGET, |(_, budget)| budget)
+}
+
+/// How many seconds the TIMEZONE of the displays is ahead of UTC at `now`
+fn utc_offset(now: u64) -> i32 {
+    TIMEZONE.timestamp_opt(now as i64, 0).unwrap().offset().fix().local_minus_utc()
+}
+
+fn room_place(building: &str, room: &str) -> Place {
+    let name = ROOM_NAMES.iter()
+        .find(|((building_id, room_id), _)| *building_id == building && *room_id == room)
+        .map_or(room, |(_, name)| name);
+    Place { id: room.to_string(), name: name.to_string() }
+}
+
 pub struct EventList {
     pub events: Vec<Event>
 }
@@ -53,65 +114,118 @@ impl EventList {
         }
     }
 
+    /// The events that aren't over yet: the ongoing ones are still shown by the displays
     pub fn get_future_events(&self) -> Vec<&Event> {
         let current_unix = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
-        let future_events: Vec<&Event> = self.events.iter().filter(|el| el.timestamp > current_unix).collect();
+        let future_events: Vec<&Event> = self.events.iter()
+            .filter(|el| el.timestamp + ONGOING_FOR.as_secs() > current_unix)
+            .collect();
         future_events
     }
 
     pub async fn send_new_states(&self) {
         let future_events = self.get_future_events();
         
-        let mut building_events: HashMap<&String, Vec<&Event>> = HashMap::new();
-        let mut room_events: HashMap<(&String, &String), Vec<&Event>> = HashMap::new();
+        let mut building_events: HashMap<&str, Vec<&Event>> = HashMap::new();
+        let mut room_events: HashMap<(&str, &str), Vec<&Event>> = HashMap::new();
         // We need to create two groups: per building and per room
         for event in future_events {
-            let building_group = match building_events.get_mut(&event.building) {
-                Some(map) => map,
-                None => {
-                    building_events.insert(&event.building, Vec::new());
-                    building_events.get_mut(&event.building).unwrap()
-                }
-            };
-            building_group.push(event);
-
-            let room_group = match room_events.get_mut(&(&event.building,&event.room)) {
-                Some(map) => map,
-                None => {
-                    room_events.insert((&event.building, &event.room), Vec::new());
-                    room_events.get_mut(&(&event.building, &event.room)).unwrap()
-                }
-            };
-            room_group.push(event);
+            building_events.entry(event.building.as_str()).or_default().push(event);
+            room_events.entry((event.building.as_str(), event.room.as_str())).or_default().push(event);
+        }
+        // The topics without events left still get an empty payload, replacing the retained one: their displays show
+        // the idle screen instead of events that are over. These are the topics of the expired events, which stay in
+        // the table (they've been published before), and the known ones
+        for event in &self.events {
+            building_events.entry(event.building.as_str()).or_default();
+            room_events.entry((event.building.as_str(), event.room.as_str())).or_default();
+        }
+        for (building, _) in BUILDING_NAMES {
+            building_events.entry(*building).or_default();
+        }
+        for ((building, room), _) in ROOM_NAMES {
+            room_events.entry((*building, *room)).or_default();
         }
         println!("{}, {}", building_events.len(), room_events.len());
+        let generated_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let utc_offset = utc_offset(generated_at);
         for (building, mut events) in building_events {
             events.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
-            EventList::send_events(events, building).await;
+            // For the room headers of the building screens
+            let mut room_names: Vec<Place> = Vec::new();
+            for event in &events {
+                if !room_names.iter().any(|place| place.id == event.room) {
+                    room_names.push(room_place(building, &event.room));
+                }
+            }
+            let mut envelope = Envelope::new(
+                generated_at,
+                TopicKind::Building,
+                building_place(building),
+                None,
+                events.into_iter().map(SEvent::from).collect(),
+            );
+            envelope.room_names = room_names;
+            if let Err(e) = envelope.apply_budget(topic_budget(building), generated_at, utc_offset, PUBLISHED_ENCODINGS) {
+                error!("Can't apply the budget of topic {}, skipping it: {}", building, e);
+                continue;
+            }
+            EventList::send_events(&envelope, building).await;
         }
 
         for ((building, room), mut events) in room_events {
             events.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
-            EventList::send_events(events, &format!("{}/{}", building, room)).await;
+            let topic = format!("{}/{}", building, room);
+            let mut envelope = Envelope::new(
+                generated_at,
+                TopicKind::Room,
+                building_place(building),
+                Some(room_place(building, room)),
+                events.into_iter().map(SEvent::from).collect(),
+            );
+            if let Err(e) = envelope.apply_budget(topic_budget(&topic), generated_at, utc_offset, PUBLISHED_ENCODINGS) {
+                error!("Can't apply the budget of topic {}, skipping it: {}", topic, e);
+                continue;
+            }
+            EventList::send_events(&envelope, &topic).await;
         }
     }
     
-    async fn send_events(events: Vec<&Event>, topic: &String) {
-        println!("Sending events on topic {}...", topic);
-        let urlencoded_topic: String = byte_serialize(topic.as_bytes()).collect();
+    async fn send_events(envelope: &Envelope, topic: &str) {
+        let client = EventList::iot_client();
+
+        for encoding in PUBLISHED_ENCODINGS {
+            let body = envelope.encode(*encoding).unwrap();
+            EventList::publish(&client, &encoding.topic(topic), encoding.content_type(), body).await;
+        }
+
+        for model in RENDERED_MODELS {
+            let pages = display_layout::render_pages(envelope, *model);
+            let pages: Vec<&[u8]> = pages.iter().map(|page| page.buffer()).collect();
+            let frame = Frame::new(envelope, *model, &pages);
+            EventList::publish(&client, &model.topic(topic), "application/octet-stream", frame.encode().unwrap()).await;
+        }
+    }
 
+    fn iot_client() -> reqwest::Client {
         let root_ca: Vec<u8> = include_bytes!("../certificates/AmazonRootCA1.pem").to_vec();
         let cert: Vec<u8> = include_bytes!("../certificates/certificate.crt").to_vec();
         let pk: Vec<u8> = include_bytes!("../certificates/private.key").to_vec();
 
-        let client = reqwest::Client::builder()
+        reqwest::Client::builder()
             .add_root_certificate(Certificate::from_pem(&root_ca).unwrap())
             .identity(Identity::from_pkcs8_pem(&cert, &pk).unwrap())
-            .build().unwrap();
+            .build().unwrap()
+    }
+
+    async fn publish(client: &reqwest::Client, topic: &str, content_type: &str, body: Vec<u8>) {
+        println!("Sending {} bytes on topic {}...", body.len(), topic);
+        let urlencoded_topic: String = byte_serialize(topic.as_bytes()).collect();
 
-        //println!("Sending total: {}, to topic {}, body: {}", format!("{}/topics/{}?qos=1", AWS_IOT_ENDPOINT, urlencoded_topic), urlencoded_topic,serde_json::to_string(&events).unwrap());
         // Retain true to allow clients subscribing in the future to fetch this message
-        let response = client.post(format!("{}/topics/{}?qos=1&retain=true", AWS_IOT_ENDPOINT, urlencoded_topic)).json(&events)
+        let response = client.post(format!("{}/topics/{}?qos=1&retain=true", AWS_IOT_ENDPOINT, urlencoded_topic))
+            .header(reqwest::header::CONTENT_TYPE, content_type)
+            .body(body)
             .send()
             .await
             .unwrap();
@@ -0,0 +1,51 @@
+use display_logic::battery;
+use esp_idf_hal::{
+    adc::{self, config::Config, AdcChannelDriver, AdcDriver, ADC1},
+    gpio::Gpio34,
+};
+use esp_idf_sys::EspError;
+use log::*;
+
+// The ADC is noisy: the voltage is the average of a few samples
+const SAMPLES: u32 = 16;
+// The FireBeetle 2 ESP32-E halves the battery voltage before GPIO34, to stay in the range of the ADC
+const DIVIDER: u32 = 2;
+
+/// The battery of the FireBeetle 2 ESP32-E, whose voltage is measured on GPIO34 (A2)
+pub struct Battery<'d> {
+    adc: AdcDriver<'d, ADC1>,
+    channel: AdcChannelDriver<'d, { adc::attenuation::DB_11 }, Gpio34>,
+}
+
+impl<'d> Battery<'d> {
+    pub fn new(adc: ADC1, pin: Gpio34) -> Result<Self, EspError> {
+        Ok(Self {
+            // Calibrated readings are in mV
+            adc: AdcDriver::new(adc, &Config::new().calibration(true))?,
+            channel: AdcChannelDriver::new(pin)?,
+        })
+    }
+
+    pub fn millivolts(&mut self) -> Result<u32, EspError> {
+        let mut total = 0;
+        for _ in 0..SAMPLES {
+            total += self.adc.read(&mut self.channel)? as u32;
+        }
+        Ok(total / SAMPLES * DIVIDER)
+    }
+
+    /// The charge left, None if it can't be measured
+    pub fn percentage(&mut self) -> Option<u8> {
+        match self.millivolts() {
+            Ok(millivolts) => {
+                let percentage = battery::percentage(millivolts);
+                info!("Battery: {} mV, {}%", millivolts, percentage);
+                Some(percentage)
+            }
+            Err(e) => {
+                warn!("Can't measure the battery voltage: {}", e);
+                None
+            }
+        }
+    }
+}
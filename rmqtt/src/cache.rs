@@ -0,0 +1,57 @@
+use display_logic::cache::{BlobStore, PayloadCache};
+use esp_idf_svc::nvs::{EspCustomNvsPartition, EspNvs, NvsCustom};
+use esp_idf_sys::EspError;
+use log::*;
+
+// Its own NVS partition (see partition-table.csv): a payload doesn't fit next to the settings and the Wi-Fi data
+const NVS_PARTITION: &str = "cache";
+const NVS_NAMESPACE: &str = "payloads";
+
+/// The payloads saved in the `cache` NVS partition
+pub struct NvsBlobStore(EspNvs<NvsCustom>);
+
+impl NvsBlobStore {
+    pub fn new() -> Result<Self, EspError> {
+        let partition = EspCustomNvsPartition::take(NVS_PARTITION)?;
+        Ok(Self(EspNvs::new(partition, NVS_NAMESPACE, true)?))
+    }
+}
+
+impl BlobStore for NvsBlobStore {
+    type Error = EspError;
+
+    fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let read = || -> Result<Option<Vec<u8>>, EspError> {
+            let Some(len) = self.0.blob_len(key)? else {
+                return Ok(None);
+            };
+            let mut buffer = vec![0; len];
+            Ok(self.0.get_blob(key, &mut buffer)?.map(|blob| blob.to_vec()))
+        };
+        match read() {
+            Ok(blob) => blob,
+            Err(e) => {
+                warn!("Can't read cached payload {} from NVS: {}", key, e);
+                None
+            }
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &[u8]) -> Result<(), EspError> {
+        self.0.set_blob(key, value)
+    }
+}
+
+pub type Cache = PayloadCache<NvsBlobStore>;
+
+/// The cache of the display topics, None if the partition can't be opened (eg. a board updated over the air, which
+/// still has the partition table of its first flash): the board then simply works without it
+pub fn open(topics: Vec<String>) -> Option<Cache> {
+    match NvsBlobStore::new() {
+        Ok(store) => Some(PayloadCache::new(store, topics)),
+        Err(e) => {
+            warn!("Payload cache not available: {}", e);
+            None
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    io::{self, BufRead, Write},
+    thread,
+    time::Duration,
+};
+
+use display_logic::settings::{self, Response, Settings, Store};
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use esp_idf_sys::EspError;
+use log::*;
+
+const NVS_NAMESPACE: &str = "display";
+// Longest setting accepted by the console, plus the NUL terminator
+const MAX_VALUE_LEN: usize = 256;
+
+/// The settings saved in the default NVS partition
+pub struct NvsStore(EspNvs<NvsDefault>);
+
+impl NvsStore {
+    pub fn new(partition: EspDefaultNvsPartition) -> Result<Self, EspError> {
+        Ok(Self(EspNvs::new(partition, NVS_NAMESPACE, true)?))
+    }
+}
+
+impl Store for NvsStore {
+    type Error = EspError;
+
+    fn get(&self, key: &str) -> Option<String> {
+        let mut buffer = [0; MAX_VALUE_LEN];
+        match self.0.get_str(key, &mut buffer) {
+            Ok(value) => value.map(|value| value.trim_end_matches('\0').to_string()),
+            Err(e) => {
+                warn!("Can't read setting {} from NVS: {}", key, e);
+                None
+            }
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), EspError> {
+        self.0.set_str(key, value)
+    }
+}
+
+/// Serves the provisioning console on the serial port (see `display_logic::settings`) in a separate thread, for as
+/// long as the board is awake.
+pub fn spawn(mut settings: Settings, mut store: NvsStore) {
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut line = String::new();
+        loop {
+            // The UART is read without blocking: until the end of the line (some terminals only send a CR), keep
+            // what has been typed so far and wait for the rest
+            let _ = stdin.lock().read_line(&mut line);
+            if !line.ends_with(['\n', '\r']) {
+                thread::sleep(Duration::from_millis(100));
+                continue;
+            }
+            let response = settings::handle_line(&line, &mut settings, &mut store);
+            line.clear();
+            match response {
+                Response::Output(lines) => {
+                    for line in lines {
+                        println!("{}", line);
+                    }
+                }
+                Response::Reboot => {
+                    println!("OK");
+                    let _ = io::stdout().flush();
+                    unsafe { esp_idf_sys::esp_restart() };
+                }
+            }
+            let _ = io::stdout().flush();
+        }
+    });
+}
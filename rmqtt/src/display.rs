@@ -0,0 +1,240 @@
+use display_layout::{
+    profile::{self, Profile},
+    Framebuffer,
+};
+use display_logic::{
+    board::Board,
+    refresh::{PanelState, Refresh, RefreshPolicy},
+};
+use display_payload::DisplayModel;
+use epd_waveshare::{
+    epd2in9_v2::Epd2in9,
+    epd4in2::Epd4in2,
+    epd5in83_v2::Epd5in83,
+    epd7in5_v2::Epd7in5,
+    prelude::{QuickRefresh, WaveshareDisplay},
+};
+use esp_idf_hal::{
+    delay::Ets,
+    gpio::{AnyIOPin, AnyInputPin, AnyOutputPin, Gpio2, Input, Output, PinDriver},
+    spi::{config::Config, SpiDeviceDriver, SpiDriver, SpiDriverConfig, SPI2},
+};
+use esp_idf_sys::EspError;
+use log::*;
+
+use crate::{Panel, PanelCommand};
+
+pub type Spi = SpiDeviceDriver<'static, SpiDriver<'static>>;
+pub type OutputPin = PinDriver<'static, AnyOutputPin, Output>;
+pub type InputPin = PinDriver<'static, AnyInputPin, Input>;
+
+/// The control pins of the panel, besides the SPI bus
+pub struct Pins {
+    pub cs: OutputPin,
+    pub busy: InputPin,
+    pub dc: OutputPin,
+    pub rst: OutputPin,
+}
+
+impl Pins {
+    /// The control pins of the panel, as wired on `board`
+    pub fn of(board: Board) -> Result<Self, EspError> {
+        let pins = board.pins();
+        // Safety: the pins of the panel are distinct, and not used by anything else (see display_logic::board)
+        unsafe {
+            Ok(Self {
+                cs: PinDriver::output(AnyOutputPin::new(pins.cs))?,
+                busy: PinDriver::input(AnyInputPin::new(pins.busy))?,
+                dc: PinDriver::output(AnyOutputPin::new(pins.dc))?,
+                rst: PinDriver::output(AnyOutputPin::new(pins.rst))?,
+            })
+        }
+    }
+}
+
+/// The SPI bus of the panel, as wired on `board`
+pub fn bus(spi: SPI2, board: Board) -> Result<Spi, EspError> {
+    let pins = board.pins();
+    let config = Config::new().baudrate(112500.into());
+    // Safety: see Pins::of
+    let (sclk, mosi) = unsafe { (AnyOutputPin::new(pins.sclk), AnyOutputPin::new(pins.mosi)) };
+    SpiDeviceDriver::new_single(
+        spi,
+        sclk,
+        mosi,
+        Option::<Gpio2>::None,
+        Option::<AnyIOPin>::None,
+        &SpiDriverConfig::default(),
+        &config,
+    )
+}
+
+/// A panel the firmware can drive: its layout profile (resolution, rotation and fonts), along with its epd-waveshare
+/// driver
+pub trait DisplayProfile: Profile {
+    type Driver: WaveshareDisplay<Spi, OutputPin, InputPin, OutputPin, OutputPin, Ets> + 'static;
+
+    /// Whether the panel can redraw only the pixels that changed, see [`DisplayProfile::show_partial`]
+    const PARTIAL_REFRESH: bool = false;
+
+    /// Resets and initializes the panel
+    fn driver(spi: &mut Spi, pins: Pins, delay: &mut Ets) -> anyhow::Result<Self::Driver> {
+        Ok(Self::Driver::new(spi, pins.cs, pins.busy, pins.dc, pins.rst, delay, None)?)
+    }
+
+    /// Full refresh: the panel flashes, clearing any ghosting
+    fn show(
+        epd: &mut Self::Driver,
+        spi: &mut Spi,
+        buffer: &[u8],
+        delay: &mut Ets,
+    ) -> anyhow::Result<()> {
+        epd.update_frame(spi, buffer, delay)?;
+        epd.display_frame(spi, delay)?;
+        Ok(())
+    }
+
+    /// Partial refresh, without flashing. Only called when `PARTIAL_REFRESH` is set
+    fn show_partial(
+        epd: &mut Self::Driver,
+        spi: &mut Spi,
+        buffer: &[u8],
+        delay: &mut Ets,
+    ) -> anyhow::Result<()> {
+        Self::show(epd, spi, buffer, delay)
+    }
+}
+
+/// Full and quick refresh of the panels implementing QuickRefresh: the panel compares the new frame with the old one,
+/// which has to be kept in sync after every refresh
+fn show_full<D>(epd: &mut D, spi: &mut Spi, buffer: &[u8], delay: &mut Ets) -> anyhow::Result<()>
+where
+    D: WaveshareDisplay<Spi, OutputPin, InputPin, OutputPin, OutputPin, Ets>
+        + QuickRefresh<Spi, OutputPin, InputPin, OutputPin, OutputPin, Ets>,
+{
+    epd.update_frame(spi, buffer, delay)?;
+    epd.display_frame(spi, delay)?;
+    epd.update_old_frame(spi, buffer, delay)?;
+    Ok(())
+}
+
+fn show_quick<D>(epd: &mut D, spi: &mut Spi, buffer: &[u8], delay: &mut Ets) -> anyhow::Result<()>
+where
+    D: QuickRefresh<Spi, OutputPin, InputPin, OutputPin, OutputPin, Ets>,
+{
+    epd.update_and_display_new_frame(spi, buffer, delay)?;
+    epd.update_old_frame(spi, buffer, delay)?;
+    Ok(())
+}
+
+impl DisplayProfile for profile::Epd2in9V2 {
+    type Driver = Epd2in9<Spi, OutputPin, InputPin, OutputPin, OutputPin, Ets>;
+    const PARTIAL_REFRESH: bool = true;
+
+    fn show(
+        epd: &mut Self::Driver,
+        spi: &mut Spi,
+        buffer: &[u8],
+        delay: &mut Ets,
+    ) -> anyhow::Result<()> {
+        show_full(epd, spi, buffer, delay)
+    }
+
+    fn show_partial(
+        epd: &mut Self::Driver,
+        spi: &mut Spi,
+        buffer: &[u8],
+        delay: &mut Ets,
+    ) -> anyhow::Result<()> {
+        show_quick(epd, spi, buffer, delay)
+    }
+}
+
+impl DisplayProfile for profile::Epd4in2 {
+    type Driver = Epd4in2<Spi, OutputPin, InputPin, OutputPin, OutputPin, Ets>;
+    const PARTIAL_REFRESH: bool = true;
+
+    fn show(
+        epd: &mut Self::Driver,
+        spi: &mut Spi,
+        buffer: &[u8],
+        delay: &mut Ets,
+    ) -> anyhow::Result<()> {
+        show_full(epd, spi, buffer, delay)
+    }
+
+    fn show_partial(
+        epd: &mut Self::Driver,
+        spi: &mut Spi,
+        buffer: &[u8],
+        delay: &mut Ets,
+    ) -> anyhow::Result<()> {
+        show_quick(epd, spi, buffer, delay)
+    }
+}
+
+impl DisplayProfile for profile::Epd5in83V2 {
+    type Driver = Epd5in83<Spi, OutputPin, InputPin, OutputPin, OutputPin, Ets>;
+}
+
+impl DisplayProfile for profile::Epd7in5V2 {
+    type Driver = Epd7in5<Spi, OutputPin, InputPin, OutputPin, OutputPin, Ets>;
+}
+
+/// What's on the panel, kept in RTC memory across deep sleep (reset on power loss)
+#[link_section = ".rtc.data"]
+static mut PANEL_STATE: PanelState = PanelState::UNKNOWN;
+
+/// Initializes the panel of the `panel` setting, ready to show the framebuffers rendered for its model. The panel
+/// chooses between a full and a partial refresh by itself, following `policy`
+pub fn open(
+    model: DisplayModel,
+    spi: Spi,
+    pins: Pins,
+    policy: RefreshPolicy,
+) -> anyhow::Result<Box<Panel<'static>>> {
+    match model {
+        DisplayModel::Epd2in9V2 => open_with(profile::Epd2in9V2, spi, pins, policy),
+        DisplayModel::Epd4in2 => open_with(profile::Epd4in2, spi, pins, policy),
+        DisplayModel::Epd5in83V2 => open_with(profile::Epd5in83V2, spi, pins, policy),
+        DisplayModel::Epd7in5V2 => open_with(profile::Epd7in5V2, spi, pins, policy),
+    }
+}
+
+fn open_with<P: DisplayProfile>(
+    profile: P,
+    mut spi: Spi,
+    pins: Pins,
+    policy: RefreshPolicy,
+) -> anyhow::Result<Box<Panel<'static>>> {
+    let mut delay = Ets;
+    let mut epd = P::driver(&mut spi, pins, &mut delay)?;
+    let model = profile.model();
+    Ok(Box::new(move |command: PanelCommand| -> anyhow::Result<()> {
+        match command {
+            PanelCommand::Show(buffer) => {
+                // Only accessed by the main thread, which owns the panel
+                let state = unsafe { &mut PANEL_STATE };
+                let refresh = match Framebuffer::from_buffer(model, buffer.to_vec()) {
+                    Some(framebuffer) if P::PARTIAL_REFRESH => {
+                        state.refresh(&policy, &framebuffer.row_hashes())
+                    }
+                    _ => Refresh::Full,
+                };
+                let partials = state.partials();
+                debug!("{:?} refresh, {} partial since the last full one", refresh, partials);
+                let shown = match refresh {
+                    Refresh::Full => P::show(&mut epd, &mut spi, buffer, &mut delay),
+                    Refresh::Partial => P::show_partial(&mut epd, &mut spi, buffer, &mut delay),
+                };
+                if shown.is_err() {
+                    // Whatever is on the panel now, the next refresh is a full one
+                    *state = PanelState::UNKNOWN;
+                }
+                shown?;
+            }
+            PanelCommand::Sleep => epd.sleep(&mut spi, &mut delay)?,
+        }
+        Ok(())
+    }))
+}
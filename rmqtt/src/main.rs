@@ -1,76 +1,156 @@
-use embedded_graphics::{
-    mono_font::MonoTextStyleBuilder,
-    prelude::{DrawTarget, Point},
-    text::{Baseline, Text, TextStyleBuilder, Alignment},
-    Drawable,
+#[cfg(feature = "battery")]
+mod battery;
+mod cache;
+mod console;
+mod display;
+mod ota;
+
+use cache::Cache;
+use display_layout::{
+    draw_banner, draw_error_glyph, draw_status_bar, profile, render_notice, Framebuffer, Style,
+};
+#[cfg(feature = "deep_sleep")]
+use display_logic::cycle::{self, Device, Shown};
+use display_logic::{
+    agenda::Agenda,
+    battery::{Level, Thresholds},
+    identity,
+    network::{AccessPoint, Backoff, Security},
+    ota::{decode_hex, Health, HealthCheck},
+    refresh::RefreshPolicy,
+    settings::Settings,
+    status::Status,
+};
+use display_payload::{
+    payload_hash, Diagnostic, DisplayModel, Encoding, Envelope, Frame, Heartbeat, PayloadError,
+    Release, ONGOING_FOR, SCHEMA_VERSION,
 };
 use embedded_svc::{
     mqtt::client::{Connection, Event, Message, MessageImpl, QoS},
     utils::mqtt::client::ConnState,
     wifi::{AuthMethod, ClientConfiguration, Configuration},
 };
-#[cfg(feature = "epd2in9_v2")]
-use epd_waveshare::epd2in9_v2::{Display2in9 as EpdDisplay, Epd2in9 as Epd};
-#[cfg(feature = "epd2in9_v2")]
-use epd_waveshare::prelude::DisplayRotation;
-#[cfg(feature = "epd5in83_v2")]
-use epd_waveshare::epd5in83_v2::{Display5in83 as EpdDisplay, Epd5in83 as Epd};
-use epd_waveshare::prelude::{Color, WaveshareDisplay};
 use esp_idf_hal::{
-    delay::{Delay, Ets},
-    gpio::{AnyIOPin, Gpio2, PinDriver},
+    delay::Delay,
+    gpio::{PinDriver, Pull},
     prelude::Peripherals,
-    spi::{config::Config, SpiDeviceDriver, SpiDriverConfig},
 };
 use esp_idf_svc::{
     eventloop::EspSystemEventLoop,
     mqtt::client::{EspMqttClient, MqttClientConfiguration},
     nvs::EspDefaultNvsPartition,
+    sntp::EspSntp,
     tls::X509,
     wifi::{BlockingWifi, EspWifi},
 };
 use esp_idf_sys::{self as _, EspError}; // If using the `binstart` feature of `esp-idf-sys`, always keep this module imported
 use log::*;
-use serde::Deserialize;
 use std::{
+    collections::BTreeMap,
     mem, slice,
     sync::mpsc::{self, Sender},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-#[cfg(feature = "epd2in9_v2")]
+// Defaults of the settings, used until they're provisioned through the serial console (see the README)
+#[cfg(feature = "wokwi")]
 pub const WIFI_SSID: &str = "Wokwi-GUEST";
-#[cfg(feature = "epd2in9_v2")]
+#[cfg(feature = "wokwi")]
 pub const WIFI_PASS: &str = "";
-#[cfg(feature = "epd5in83_v2")]
+#[cfg(not(feature = "wokwi"))]
 pub const WIFI_SSID: &str = ""; // FILL YOUR SSID HERE
-#[cfg(feature = "epd5in83_v2")]
+#[cfg(not(feature = "wokwi"))]
 pub const WIFI_PASS: &str = ""; // FILL YOUR PW HERE
 
 // for AWS IoT Core, be sure to use the mqtts protocol and use the -ats endpoint!
 pub const MQTT_ENDPOINT: &str = "";
-// The MQTT client id MUST be unique, or else the different devices will kick each other out with an undocumented error code 119
-#[cfg(feature = "epd2in9_v2")]
-pub const MQTT_CLIENT_ID: &str = "esp32-epaper-main-wokwi";
-#[cfg(feature = "epd5in83_v2")]
-pub const MQTT_CLIENT_ID: &str = "esp32-epaper-main-real";
-pub const MQTT_TOPIC_NAME: &str = "F3/P6";
-
-// Display points
-#[cfg(feature = "epd2in9_v2")]
-pub const DISPLAY_CENTER: i32 = 148;
-#[cfg(feature = "epd2in9_v2")]
-pub const DISPLAY_END: i32 = 296;
-#[cfg(feature = "epd2in9_v2")]
-pub const FONT_HEIGHT: i32 = 13;
-
-#[cfg(feature = "epd5in83_v2")]
-pub const DISPLAY_CENTER: i32 = 324;
-#[cfg(feature = "epd5in83_v2")]
-pub const DISPLAY_END: i32 = 648;
-#[cfg(feature = "epd5in83_v2")]
-pub const FONT_HEIGHT: i32 = 20;
+// The MQTT client id MUST be unique, or else the different devices will kick each other out with an undocumented error
+// code 119: it defaults to one built from the MAC address of the board
+// The building and the rooms shown by the display, see display_logic::identity::Identity
+pub const BUILDING: &str = "F3";
+pub const ROOMS: &str = "P6";
+// The e-paper panel wired to the board, see DisplayModel::name: Wokwi only simulates the 2.9" one
+#[cfg(feature = "wokwi")]
+pub const PANEL: &str = "epd2in9_v2";
+#[cfg(not(feature = "wokwi"))]
+pub const PANEL: &str = "epd5in83_v2";
+// The ESP32 board, which decides the pins of the panel, see display_logic::board::Board
+#[cfg(feature = "wokwi")]
+pub const BOARD: &str = "wokwi_devkit";
+#[cfg(not(feature = "wokwi"))]
+pub const BOARD: &str = "firebeetle2";
+// Displays check in at least this often, even if the payload says that its content isn't going to change
+pub const MAX_SLEEP: Duration = Duration::from_secs(60 * 60);
+// Boards in deep sleep mode sleep at least this long after showing a payload, even if it's about to change: the server
+// might not have published the new content yet
+pub const MIN_SLEEP: Duration = Duration::from_secs(60);
+// How long a board in deep sleep mode waits for the retained payload after subscribing
+pub const MESSAGE_TIMEOUT: Duration = Duration::from_secs(30);
+// How long a board in deep sleep mode sleeps after a failed refresh (eg. no network) before trying again, doubling
+// after each consecutive failure
+pub const RETRY_SLEEP: Backoff = Backoff {
+    initial: Duration::from_secs(5 * 60),
+    max: MAX_SLEEP,
+};
+// After how many consecutive refreshes without network a board in deep sleep mode replaces the schedule on screen
+// with a notice
+pub const UNREACHABLE_NOTICE_AFTER: u32 = 3;
+// How long an always connected board waits before trying to reconnect to the Wi-Fi, doubling after each failure
+pub const WIFI_BACKOFF: Backoff = Backoff {
+    initial: Duration::from_secs(5),
+    max: Duration::from_secs(5 * 60),
+};
+// How often an always connected board checks that it's still connected to the Wi-Fi
+pub const WIFI_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+// How long each page stays on screen when the events don't fit on a single one. Keep in mind that a page change
+// redraws most of the screen, so it's a full refresh of the panel
+pub const PAGE_DURATION: Duration = Duration::from_secs(30);
+// On the panels that support it, a partial refresh (no flashing, less energy) is used when at most a quarter of the
+// screen changes, eg. the status bar or the label of an event. Every 10 partial refreshes, a full one clears the
+// ghosting they leave behind
+pub const REFRESH_POLICY: RefreshPolicy = RefreshPolicy {
+    full_every: 10,
+    max_changed_percent: 25,
+};
+// After how long without a new payload the status bar warns that the schedule might not be up to date
+pub const STALE_AFTER: Duration = Duration::from_secs(2 * 60 * 60);
+// Below the critical charge, the board stops refreshing the schedule and shows a low battery screen, until the battery
+// is charged back to the low threshold
+pub const BATTERY_THRESHOLDS: Thresholds = Thresholds {
+    low: 20,
+    critical: 5,
+};
+// How often an always connected board publishes its heartbeat. In deep sleep mode, it's published at every wake-up
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15 * 60);
+// How often an always connected board measures its battery
+pub const BATTERY_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+// How the schedule evolves between two payloads: events are shown as ongoing ("in corso") for an hour after their
+// start, like the lambda keeps publishing them, then hidden, and get a countdown ("tra 20 min") in the hour before
+// it, every 10 minutes
+pub const AGENDA: Agenda = Agenda {
+    ongoing_for: ONGOING_FOR,
+    countdown_from: Duration::from_secs(60 * 60),
+    countdown_step: Duration::from_secs(10 * 60),
+};
+// POSIX TZ of the displays, for the time of the last update in the status bar (Europe/Rome)
+pub const TIMEZONE: &str = "CET-1CEST,M3.5.0,M10.5.0/3";
+#[cfg(not(feature = "postcard"))]
+pub const PAYLOAD_ENCODING: Encoding = Encoding::Json;
+#[cfg(feature = "postcard")]
+pub const PAYLOAD_ENCODING: Encoding = Encoding::Postcard;
+
+// The firmware releases are announced per variant (see the README): give a different name to every combination of
+// features in use. The panel is a setting, the same image drives all of them
+pub const FIRMWARE_VARIANT: &str = "display";
+// Hex encoded Ed25519 public key the firmware releases must be signed with. Over-the-air updates are disabled while
+// it's empty
+pub const FIRMWARE_PUBLIC_KEY: &str = "";
+// How long a newly installed firmware has to show a payload: past that, the board rolls back to the previous one.
+// Long enough not to roll back a working firmware during a short network outage
+pub const HEALTH_CHECK: HealthCheck = HealthCheck {
+    timeout: Duration::from_secs(30 * 60),
+};
 
 fn main() -> anyhow::Result<()> {
     // It is necessary to call this function once. Otherwise some patches to the runtime
@@ -78,134 +158,1021 @@ fn main() -> anyhow::Result<()> {
     esp_idf_sys::link_patches();
     // Bind the log crate to the ESP Logging facilities
     esp_idf_svc::log::EspLogger::initialize_default();
+    info!("Firmware {} ({})", env!("CARGO_PKG_VERSION"), FIRMWARE_VARIANT);
+    let unverified = ota::is_unverified();
 
     let peripherals = Peripherals::take().unwrap();
     let sys_loop = EspSystemEventLoop::take()?;
     let nvs = EspDefaultNvsPartition::take()?;
 
+    let store = console::NvsStore::new(nvs.clone())?;
+    let settings = Settings::load(&store, default_settings());
+    console::spawn(settings.clone(), store);
+
     Delay::delay_ms(3000);
+    // Boot button, available on every supported board
+    let mut button = PinDriver::input(peripherals.pins.gpio0)?;
+    button.set_pull(Pull::Up)?;
+    let (model, board) = match (settings.display_model(), settings.board()) {
+        (Some(model), Some(board)) if settings.is_complete() && !button.is_low() => (model, board),
+        _ => {
+            // Only the console is served, until the board is rebooted with the new settings
+            warn!("Provisioning mode: configure the display through the serial console (type help)");
+            loop {
+                thread::sleep(Duration::from_secs(1));
+            }
+        }
+    };
+
     // Blocking so that we can block until the IP is obtained
-    let mut wifi = BlockingWifi::wrap(
+    let wifi = BlockingWifi::wrap(
         EspWifi::new(peripherals.modem, sys_loop.clone(), Some(nvs))?,
         sys_loop,
     )?;
 
-    configure_wifi(&mut wifi)?;
+    // The clock is set in the background once the Wi-Fi is connected
+    std::env::set_var("TZ", TIMEZONE);
+    unsafe { esp_idf_sys::tzset() };
+    let _sntp = EspSntp::new_default()?;
 
-    Delay::delay_ms(3000);
-
-    info!("Configuring the E-Ink display...");
-    let mut display = EpdDisplay::default();
-
-    let spi = peripherals.spi2;
-
-    // Firebeetle pins
-    let sclk = peripherals.pins.gpio18;
-    let serial_out = peripherals.pins.gpio23;
-    // Right now we assume the 2in9 screen is wokwi and the other is real hardware, so we use the same feature flag for pin configuration, kinda hacky
-    #[cfg(feature = "epd5in83_v2")]
-    let cs = PinDriver::output(peripherals.pins.gpio14)?;
-    #[cfg(feature = "epd2in9_v2")]
-    let cs = PinDriver::output(peripherals.pins.gpio5)?;
-
-    let busy_in = PinDriver::input(peripherals.pins.gpio4)?;
-    let dc = PinDriver::output(peripherals.pins.gpio22)?;
-    let rst = PinDriver::output(peripherals.pins.gpio21)?;
-
-    let config = Config::new().baudrate(112500.into());
-    let mut device = SpiDeviceDriver::new_single(
-        spi,
-        sclk,
-        serial_out,
-        Option::<Gpio2>::None,
-        Option::<AnyIOPin>::None,
-        &SpiDriverConfig::default(),
-        &config,
-    )?;
+    info!("Configuring the E-Ink display ({} on {})...", model.name(), board.name());
 
-    let mut delay = Ets;
+    let device = display::bus(peripherals.spi2, board)?;
+    let pins = display::Pins::of(board)?;
 
     Delay::delay_ms(3000);
-    let mut epd = Epd::new(&mut device, cs, busy_in, dc, rst, &mut delay, None)?;
+    let mut panel = display::open(model, device, pins, REFRESH_POLICY)?;
     info!("E-Ink display init completed!");
 
+    let cache = cache::open(display_topics(&settings, model));
+
+    #[cfg(feature = "battery")]
+    let mut battery = battery::Battery::new(peripherals.adc1, peripherals.pins.gpio34)?;
+    #[cfg(feature = "battery")]
+    let battery_percentage = move || battery.percentage();
+    #[cfg(not(feature = "battery"))]
+    let battery_percentage = || None;
+
+    #[cfg(feature = "deep_sleep")]
+    run_refresh_cycle(wifi, &settings, model, &mut *panel, cache, battery_percentage, unverified);
+
+    // Pressing the boot button shows the next page
+    #[cfg(not(feature = "deep_sleep"))]
+    run_continuously(
+        wifi,
+        &settings,
+        model,
+        &mut *panel,
+        cache,
+        || button.is_low(),
+        battery_percentage,
+        unverified,
+    )
+}
+
+fn default_settings() -> Settings {
+    Settings {
+        wifi_ssid: WIFI_SSID.to_string(),
+        wifi_pass: WIFI_PASS.to_string(),
+        mqtt_endpoint: MQTT_ENDPOINT.to_string(),
+        mqtt_client_id: identity::default_client_id(mac_address()),
+        building: BUILDING.to_string(),
+        rooms: ROOMS.to_string(),
+        panel: PANEL.to_string(),
+        board: BOARD.to_string(),
+    }
+}
+
+/// The MAC address burnt in the eFuses of the board, unique to every ESP32
+fn mac_address() -> [u8; 6] {
+    let mut mac = [0; 6];
+    unsafe { esp_idf_sys::esp_efuse_mac_get_default(mac.as_mut_ptr()) };
+    mac
+}
+
+/// The topics the board subscribes to.
+/// Boards rendering locally get the events, the others the framebuffer rendered by the server for their panel.
+fn display_topics(settings: &Settings, model: DisplayModel) -> Vec<String> {
+    let topics = settings.identity().topics();
+    #[cfg(not(feature = "server_render"))]
+    return topics.iter().map(|topic| PAYLOAD_ENCODING.topic(topic)).collect();
+    #[cfg(feature = "server_render")]
+    {
+        // Framebuffers can't be merged: the server only renders room and building screens
+        if topics.len() > 1 {
+            warn!("Hallway displays need local rendering, only showing {}", topics[0]);
+        }
+        vec![model.topic(&topics[0])]
+    }
+}
+
+/// What can be asked to the e-paper panel
+enum PanelCommand<'a> {
+    /// Draws a framebuffer in the native layout of the panel
+    Show(&'a [u8]),
+    /// Low power mode, until the next refresh
+    Sleep,
+}
+
+type Panel<'a> = dyn FnMut(PanelCommand) -> anyhow::Result<()> + 'a;
+
+/// A message received from the MQTT broker: its topic and its payload
+type Received = (String, Vec<u8>);
+
+type MqttClient = EspMqttClient<ConnState<MessageImpl, EspError>>;
+
+/// Stays connected to the MQTT broker, showing every payload as soon as it's received and rotating the pages
+#[cfg(not(feature = "deep_sleep"))]
+fn run_continuously(
+    mut wifi: BlockingWifi<EspWifi<'static>>,
+    settings: &Settings,
+    model: DisplayModel,
+    panel: &mut Panel,
+    mut cache: Option<Cache>,
+    mut page_button_pressed: impl FnMut() -> bool,
+    mut battery_percentage: impl FnMut() -> Option<u8>,
+    mut unverified: bool,
+) -> anyhow::Result<()> {
+    let started_at = Instant::now();
+    // The last content of each topic, merged into the one shown. The payloads cached in flash are shown right away,
+    // until the network is up
+    let mut contents = cached_contents(cache.as_ref());
+    let mut content = merge(&contents.values().cloned().collect::<Vec<_>>());
+    let mut status = Status::default();
+    let shown = content
+        .as_ref()
+        .and_then(|content| render_page(content, 0, &status, model).ok());
+    // Whether the panel shows the content as it is, without a notice over it: a payload already cached is then on
+    // screen
+    let mut drawn = false;
+    if let Some(buffer) = &shown {
+        info!("Showing the cached schedule");
+        match panel(PanelCommand::Show(buffer)) {
+            Ok(()) => drawn = true,
+            Err(e) => error!("Can't show the cached schedule: {:#}", e),
+        }
+    }
+    let unverified_since = unverified.then_some(started_at);
+    connect_with_backoff(&mut wifi, settings, model, panel, shown, unverified_since);
+
     //Set up a channel to send messages received from the MQTT queue (separate thread) to the main thread, to display them on the e-paper module
     info!("Setting up the MQTT client...");
-    let (sender, receiver) = mpsc::channel::<String>();
-    let _mqtt_client: EspMqttClient<ConnState<MessageImpl, EspError>> = setup_mqtt_client(sender)?;
+    let (sender, receiver) = mpsc::channel::<Received>();
+    let mut mqtt_client: MqttClient = setup_mqtt_client(sender, settings, model)?;
 
+    // Whether the last payload couldn't be shown: the screen keeps the previous content, with an error glyph
+    let mut rejected = false;
+    // Whether the status bar on screen warns that the schedule is stale
+    let mut stale_shown = false;
+    let mut battery_level = Level::Normal;
+    let mut battery_checked_at: Option<Instant> = None;
+    // When the events on screen are due to change (an event ends, starts or its countdown ticks)
+    let mut next_label_change_at = content.as_ref().and_then(next_label_change);
+    let mut last_payload_hash: Option<String> = None;
+    let mut heartbeat_sent_at: Option<Instant> = None;
+    let mut page = 0;
+    let mut page_shown_at = Instant::now();
+    let mut button_was_pressed = false;
+    let mut wifi_checked_at = Instant::now();
     loop {
-        Delay::delay_ms(1000);
-        // Check for new messages every 3 seconds for 2 seconds
-        let message = receiver.recv_timeout(Duration::from_millis(2000));
-        if let Ok(message) = message {
-            info!("Message received in main thread: {:?}", message);
-            let events: Vec<SEvent> = serde_json::from_str(message.as_str())?;
-            // Dummy events for testing the display
-            // let events: Vec<SEvent> = vec![
-            //     SEvent {
-            //         id: "1".to_string(),
-            //         title: "Test 1: the test".to_string(),
-            //         datetime: String::from("2022/05/20 11:00"),
-            //         timestamp: 1688690076,
-            //         building: "F3".to_string(),
-            //         room: "P3".to_string(),
-            //     },
-            //     SEvent {
-            //         id: "2".to_string(),
-            //         title: "Test 2: the other test".to_string(),
-            //         datetime: String::from("2022/05/20 11:00"),
-            //         timestamp: 1688690076,
-            //         building: "F3".to_string(),
-            //         room: "P3".to_string(),
-            //     },
-            // ];
-
-            display.clear(Color::White)?;
-            let mut i = 0;
-            let mut draw_room = true;
-            for event in events {
-                if draw_room {
-                    draw_text(&mut display, format!(" {} ({}) ", event.room, event.building).as_str(), DISPLAY_CENTER, i, Alignment::Center);
-                    i += 20;
-                    draw_room = false;
+        let mut refresh = false;
+        if wifi_checked_at.elapsed() >= WIFI_CHECK_INTERVAL {
+            if !wifi.is_connected().unwrap_or(false) {
+                warn!("Wifi disconnected");
+                let shown = content
+                    .as_ref()
+                    .and_then(|content| render_page(content, page, &status, model).ok());
+                let unverified_since = unverified.then_some(started_at);
+                connect_with_backoff(&mut wifi, settings, model, panel, shown, unverified_since);
+                drawn = false;
+                // Removes the notice; the MQTT client reconnects by itself
+                refresh = content.is_some();
+            }
+            wifi_checked_at = Instant::now();
+        }
+
+        let battery_check_due =
+            battery_checked_at.map_or(true, |checked_at| checked_at.elapsed() >= BATTERY_CHECK_INTERVAL);
+        if battery_check_due {
+            status.battery = battery_percentage();
+            if let Some(percentage) = status.battery {
+                let level = BATTERY_THRESHOLDS.level(percentage, battery_level);
+                if level == Level::Critical && battery_level != Level::Critical {
+                    warn!("Battery critical ({}%), not refreshing the schedule", percentage);
+                    let screen = low_battery_screen(model, percentage);
+                    drawn = false;
+                    if let Err(e) = panel(PanelCommand::Show(&screen)) {
+                        error!("Can't show the low battery screen: {:#}", e);
+                    }
+                }
+                // Back to the schedule once charged
+                refresh |= level != Level::Critical && battery_level == Level::Critical;
+                battery_level = level;
+            }
+            battery_checked_at = Some(Instant::now());
+        }
+
+        // Short timeout to keep the page button responsive
+        let message = receiver.recv_timeout(Duration::from_millis(100));
+        if let Ok((topic, message)) = message {
+            info!("Message received in main thread on {}: {} bytes", topic, message.len());
+            if topic == firmware_topic() {
+                update_firmware(&message);
+                continue;
+            }
+            match parse_message(&message) {
+                Ok(Some(new_content)) => {
+                    status.synced_at = now();
+                    last_payload_hash = Some(payload_hash(&message));
+                    let on_screen = contents.contains_key(&topic)
+                        && cache.as_ref().map_or(false, |cache| {
+                            cache.is_on_screen(&topic, &message, drawn && !rejected)
+                        });
+                    if on_screen {
+                        info!("Payload unchanged on {}, not refreshing", topic);
+                        // Eg. the retained payload sent again after the reboot of an update: it's shown all the same
+                        if unverified {
+                            ota::mark_valid();
+                            unverified = false;
+                        }
+                    } else {
+                        save_to_cache(cache.as_mut(), &topic, &message);
+                        contents.insert(topic, new_content);
+                        content = merge(&contents.values().cloned().collect::<Vec<_>>());
+                        page = 0;
+                        rejected = false;
+                        refresh = true;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    // Keep showing the last good content: the schedule is likely still accurate
+                    error!("Rejected payload on {}: {:#}", topic, e);
+                    report_rejected(&mut mqtt_client, settings, &topic, &e, &message);
+                    refresh = !rejected && content.is_some();
+                    rejected = true;
+                }
+            }
+        }
+
+        let button_pressed = page_button_pressed();
+        let next_page_requested = button_pressed && !button_was_pressed;
+        button_was_pressed = button_pressed;
+        if let Some(content) = &content {
+            let pages = page_count(content, model);
+            if pages > 1 && (next_page_requested || page_shown_at.elapsed() >= PAGE_DURATION) {
+                page = (page + 1) % pages;
+                refresh = true;
+            }
+        }
+
+        // Without a new payload for too long: the status bar turns into a warning
+        if status.is_stale(now(), STALE_AFTER) != stale_shown {
+            refresh = true;
+        }
+        if let (Some(change_at), Some(now)) = (next_label_change_at, now()) {
+            refresh |= now >= change_at;
+        }
+
+        if heartbeat_sent_at.map_or(true, |sent_at| sent_at.elapsed() >= HEARTBEAT_INTERVAL) {
+            status.rssi = rssi();
+            let hash = last_payload_hash.clone();
+            publish_heartbeat(&mut mqtt_client, settings, &status, hash, HEARTBEAT_INTERVAL);
+            heartbeat_sent_at = Some(Instant::now());
+        }
+
+        if unverified && HEALTH_CHECK.check(false, started_at.elapsed()) == Health::Failed {
+            ota::rollback();
+        }
+        if battery_level == Level::Critical {
+            continue;
+        }
+        if let (true, Some(content)) = (refresh, &content) {
+            info!("Showing page {}/{}", page + 1, page_count(content, model));
+            status.rssi = rssi();
+            stale_shown = status.is_stale(now(), STALE_AFTER);
+            drawn = false;
+            match show_page(content, page, &status, rejected, model, panel) {
+                Ok(()) if unverified => {
+                    ota::mark_valid();
+                    unverified = false;
+                    drawn = true;
+                }
+                Ok(()) => drawn = true,
+                Err(e) => error!("Can't show page {}: {:#}", page + 1, e),
+            }
+            next_label_change_at = next_label_change(content);
+            page_shown_at = Instant::now();
+        }
+    }
+}
+
+/// Draws a page of the content on the panel, with the error glyph if the last payload has been rejected
+#[cfg(not(feature = "deep_sleep"))]
+fn show_page(
+    content: &Content,
+    page: usize,
+    status: &Status,
+    rejected: bool,
+    model: DisplayModel,
+    panel: &mut Panel,
+) -> anyhow::Result<()> {
+    let mut buffer = render_page(content, page, status, model)?;
+    if rejected {
+        buffer = with_error_glyph(model, buffer);
+    }
+    panel(PanelCommand::Show(&buffer))
+}
+
+/// Connects to the Wi-Fi, retrying with backoff until an access point can be reached. Meanwhile, the screen tells
+/// that the network is unreachable: over the page `shown` if there's one, in place of it otherwise.
+/// A firmware `unverified_since` its boot is rolled back if it can't connect within the health check: it might be
+/// the one that broke the Wi-Fi.
+#[cfg(not(feature = "deep_sleep"))]
+fn connect_with_backoff(
+    wifi: &mut BlockingWifi<EspWifi<'static>>,
+    settings: &Settings,
+    model: DisplayModel,
+    panel: &mut Panel,
+    shown: Option<Vec<u8>>,
+    unverified_since: Option<Instant>,
+) {
+    let mut failures = 0;
+    while let Err(e) = configure_wifi(wifi, settings) {
+        if let Some(since) = unverified_since {
+            if HEALTH_CHECK.check(false, since.elapsed()) == Health::Failed {
+                ota::rollback();
+            }
+        }
+        failures += 1;
+        let delay = WIFI_BACKOFF.delay(failures);
+        warn!("Network unreachable ({}), retrying in {:?}", e, delay);
+        // A single refresh of the panel, not one per attempt
+        if failures == 1 {
+            let notice = unreachable_screen(settings, model, shown.clone(), delay);
+            if let Err(e) = panel(PanelCommand::Show(&notice)) {
+                error!("Can't show the unreachable notice: {:#}", e);
+            }
+        }
+        thread::sleep(delay);
+    }
+}
+
+/// The framebuffer telling that the network can't be reached: a banner over the page `shown` if there's one, so that
+/// the schedule stays readable, or a full screen notice
+fn unreachable_screen(
+    settings: &Settings,
+    model: DisplayModel,
+    shown: Option<Vec<u8>>,
+    retry_in: Duration,
+) -> Vec<u8> {
+    let style = style(model);
+    // Drawing on a framebuffer can't fail
+    match shown.and_then(|buffer| Framebuffer::from_buffer(model, buffer)) {
+        Some(mut framebuffer) => {
+            draw_banner("Rete non raggiungibile", &style, &mut framebuffer).unwrap();
+            framebuffer.into_buffer()
+        }
+        None => {
+            let ssids: Vec<String> = settings.access_points().into_iter().map(|ap| ap.ssid).collect();
+            let details = [
+                format!("Reti provate: {}", ssids.join(", ")),
+                format!("Nuovo tentativo tra {}", format_delay(retry_in)),
+            ];
+            let mut framebuffer = Framebuffer::new(model);
+            render_notice("Rete non raggiungibile", &details, &style, &mut framebuffer).unwrap();
+            framebuffer.into_buffer()
+        }
+    }
+}
+
+/// The framebuffer telling that the battery has to be charged, in place of the schedule
+fn low_battery_screen(model: DisplayModel, percentage: u8) -> Vec<u8> {
+    let details = [
+        "Ricaricare la batteria del display".to_string(),
+        format!("Carica residua: {}%", percentage),
+    ];
+    let mut framebuffer = Framebuffer::new(model);
+    // Drawing on a framebuffer can't fail
+    render_notice("Batteria scarica", &details, &style(model), &mut framebuffer).unwrap();
+    framebuffer.into_buffer()
+}
+
+/// The control topic announcing the firmware releases for this board
+fn firmware_topic() -> String {
+    Release::topic(FIRMWARE_VARIANT)
+}
+
+/// The key the firmware releases must be signed with, None if over-the-air updates are disabled
+fn firmware_public_key() -> Option<[u8; 32]> {
+    decode_hex(FIRMWARE_PUBLIC_KEY)
+}
+
+/// Installs the release announced on the control topic if it's newer: the board restarts on success
+fn update_firmware(message: &[u8]) {
+    let Some(public_key) = firmware_public_key() else {
+        return;
+    };
+    if let Err(e) = ota::handle_release(message, &public_key) {
+        error!("Firmware update failed: {:#}", e);
+    }
+}
+
+/// Draws the error glyph over the framebuffer of a page
+fn with_error_glyph(model: DisplayModel, buffer: Vec<u8>) -> Vec<u8> {
+    match Framebuffer::from_buffer(model, buffer.clone()) {
+        Some(mut framebuffer) => {
+            // Drawing on a framebuffer can't fail
+            draw_error_glyph(&style(model), &mut framebuffer).unwrap();
+            framebuffer.into_buffer()
+        }
+        None => buffer,
+    }
+}
+
+/// The contents of the payloads cached in flash, by topic
+fn cached_contents(cache: Option<&Cache>) -> BTreeMap<String, Content> {
+    let Some(cache) = cache else {
+        return BTreeMap::new();
+    };
+    cache
+        .payloads()
+        .into_iter()
+        .filter_map(|cached| match parse_message(&cached.payload) {
+            Ok(content) => Some((cached.topic, content?)),
+            // Eg. cached by a previous firmware with another PAYLOAD_ENCODING
+            Err(e) => {
+                warn!("Ignoring the cached payload of {}: {:#}", cached.topic, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Saves a valid payload in flash, to be shown at the next reboot. Best effort: a failure is only logged
+fn save_to_cache(cache: Option<&mut Cache>, topic: &str, message: &[u8]) {
+    if let Some(cache) = cache {
+        match cache.save(topic, message) {
+            Ok(true) => info!("Payload of {} saved in the cache", topic),
+            Ok(false) => {}
+            Err(e) => warn!("Can't save the payload of {} in the cache: {}", topic, e),
+        }
+    }
+}
+
+/// Publishes a payload that couldn't be shown on the diagnostics topic of the display, so that the problem doesn't
+/// go unnoticed. Best effort: a failure is only logged
+fn report_rejected(
+    client: &mut MqttClient,
+    settings: &Settings,
+    topic: &str,
+    error: &anyhow::Error,
+    payload: &[u8],
+) {
+    let client_id = &settings.mqtt_client_id;
+    let diagnostic = Diagnostic::new(client_id, topic, &format!("{:#}", error), payload);
+    let published = diagnostic.encode().map_err(anyhow::Error::from).and_then(|diagnostic| {
+        client.publish(&Diagnostic::topic(client_id), QoS::AtMostOnce, false, &diagnostic)?;
+        Ok(())
+    });
+    if let Err(e) = published {
+        warn!("Can't report the rejected payload: {:#}", e);
+    }
+}
+
+/// Publishes the heartbeat of the board on its telemetry topic, telling when the next one is due. Best effort: a
+/// failure is only logged
+fn publish_heartbeat(
+    client: &mut MqttClient,
+    settings: &Settings,
+    status: &Status,
+    last_payload_hash: Option<String>,
+    interval: Duration,
+) {
+    let heartbeat = Heartbeat {
+        schema_version: SCHEMA_VERSION,
+        client_id: settings.mqtt_client_id.clone(),
+        firmware_version: env!("CARGO_PKG_VERSION").to_string(),
+        battery: status.battery,
+        rssi: status.rssi,
+        uptime: unsafe { esp_idf_sys::esp_timer_get_time() } as u64 / 1_000_000,
+        last_payload_hash,
+        interval: interval.as_secs(),
+    };
+    info!("Heartbeat: {:?}", heartbeat);
+    let topic = Heartbeat::topic(&settings.mqtt_client_id);
+    let published = heartbeat.encode().map_err(anyhow::Error::from).and_then(|heartbeat| {
+        client.publish(&topic, QoS::AtMostOnce, false, &heartbeat)?;
+        Ok(())
+    });
+    if let Err(e) = published {
+        warn!("Can't publish the heartbeat: {:#}", e);
+    }
+}
+
+fn format_delay(delay: Duration) -> String {
+    match delay.as_secs() {
+        secs if secs < 60 => format!("{} s", secs),
+        secs => format!("{} min", secs / 60),
+    }
+}
+
+/// Consecutive failed refresh cycles, kept in RTC memory across deep sleep (reset on power loss)
+#[cfg(feature = "deep_sleep")]
+#[link_section = ".rtc.data"]
+static mut FAILED_CYCLES: u32 = 0;
+/// Level of the battery at the last wake-up, in RTC memory as well
+#[cfg(feature = "deep_sleep")]
+#[link_section = ".rtc.data"]
+static mut BATTERY_LEVEL: Level = Level::Normal;
+/// The schedule on the panel, in RTC memory as well: None while the panel shows something else (a notice), or what
+/// it shows isn't known
+#[cfg(feature = "deep_sleep")]
+#[link_section = ".rtc.data"]
+static mut ON_SCREEN: Option<OnScreen> = None;
+
+#[cfg(feature = "deep_sleep")]
+#[derive(Clone, Copy)]
+struct OnScreen {
+    /// The payloads shown, see `cycle_hash`
+    payloads: u64,
+    /// The time the labels of the events have been drawn for (see `current`)
+    drawn_at: u64,
+}
+
+/// A fingerprint of the payloads received in a refresh cycle, with their topics
+#[cfg(feature = "deep_sleep")]
+fn cycle_hash(topics: &[String], messages: &[Vec<u8>]) -> u64 {
+    let hashes: String = topics
+        .iter()
+        .zip(messages)
+        .map(|(topic, message)| format!("{}={};", topic, payload_hash(message)))
+        .collect();
+    // Always 16 hex digits
+    u64::from_str_radix(&payload_hash(hashes.as_bytes()), 16).unwrap_or_default()
+}
+
+/// Runs a single refresh cycle, then puts the board in deep sleep until the next one: waking up from deep sleep
+/// restarts the firmware from `main`.
+/// Only the first page is shown in this mode.
+#[cfg(feature = "deep_sleep")]
+fn run_refresh_cycle(
+    wifi: BlockingWifi<EspWifi<'static>>,
+    settings: &Settings,
+    model: DisplayModel,
+    panel: &mut Panel,
+    cache: Option<Cache>,
+    mut battery_percentage: impl FnMut() -> Option<u8>,
+    unverified: bool,
+) -> ! {
+    let battery = battery_percentage();
+    if let Some(percentage) = battery {
+        // Only accessed by the main thread
+        let previous = unsafe { BATTERY_LEVEL };
+        let level = BATTERY_THRESHOLDS.level(percentage, previous);
+        unsafe { BATTERY_LEVEL = level };
+        if level == Level::Critical {
+            // Not even the Wi-Fi: check again later, in case the battery is being charged
+            warn!("Battery critical ({}%), skipping the refresh", percentage);
+            if previous != Level::Critical {
+                unsafe { ON_SCREEN = None };
+                let _ = panel(PanelCommand::Show(&low_battery_screen(model, percentage)));
+            }
+            let _ = panel(PanelCommand::Sleep);
+            unsafe { esp_idf_sys::esp_deep_sleep(MAX_SLEEP.as_micros() as u64) }
+        }
+    }
+    // After a reset rather than a wake-up: the cached schedule until the payloads are received
+    let reset_reason = unsafe { esp_idf_sys::esp_reset_reason() };
+    if reset_reason != esp_idf_sys::esp_reset_reason_t_ESP_RST_DEEPSLEEP {
+        let contents = cached_contents(cache.as_ref());
+        let shown = merge(&contents.into_values().collect::<Vec<_>>())
+            .map(|content| render_page(&content, 0, &Status::default(), model));
+        if let Some(Ok(buffer)) = shown {
+            info!("Showing the cached schedule");
+            // Then refreshed by the cycle, with the labels and the status bar up to date
+            unsafe { ON_SCREEN = None };
+            if let Err(e) = panel(PanelCommand::Show(&buffer)) {
+                error!("Can't show the cached schedule: {:#}", e);
+            }
+        }
+    }
+    let config = cycle::Config {
+        message_timeout: MESSAGE_TIMEOUT,
+        retry: RETRY_SLEEP,
+        unreachable_notice_after: UNREACHABLE_NOTICE_AFTER,
+        min_sleep: MIN_SLEEP,
+        max_sleep: MAX_SLEEP,
+    };
+    let mut board = Board {
+        wifi,
+        settings,
+        model,
+        mqtt: None,
+        received_topics: Vec::new(),
+        release: None,
+        last_payload_hash: None,
+        cache,
+        status: Status {
+            battery,
+            ..Default::default()
+        },
+        panel,
+    };
+    let started_at = Instant::now();
+    let sleep = loop {
+        // Only accessed by the main thread
+        let failed_cycles = unsafe { FAILED_CYCLES };
+        let sleep = config.run(&mut board, failed_cycles, |state| match state {
+            cycle::State::Show(messages) => {
+                info!("Refresh cycle: showing {} messages", messages.len())
+            }
+            state => info!("Refresh cycle: {:?}", state),
+        });
+        unsafe {
+            FAILED_CYCLES = match sleep.outcome.is_failure() {
+                true => failed_cycles.saturating_add(1),
+                false => 0,
+            }
+        };
+        if !unverified {
+            break sleep;
+        }
+        let shown = sleep.outcome == cycle::Outcome::Shown;
+        match HEALTH_CHECK.check(shown, started_at.elapsed()) {
+            Health::Valid => {
+                ota::mark_valid();
+                break sleep;
+            }
+            Health::Failed => ota::rollback(),
+            Health::Unverified => {
+                // Waking up from deep sleep is a restart, which rolls back a firmware that hasn't been marked
+                // valid yet: stay awake until the next cycle instead
+                let left = HEALTH_CHECK.timeout.saturating_sub(started_at.elapsed());
+                let wait = sleep.duration.min(left);
+                warn!("Unverified firmware: {:?}, trying again in {:?}", sleep.outcome, wait);
+                thread::sleep(wait);
+            }
+        }
+    };
+    // Due again at the next wake-up
+    if let Some((client, _)) = &mut board.mqtt {
+        board.status.rssi = rssi();
+        let hash = board.last_payload_hash.clone();
+        publish_heartbeat(client, settings, &board.status, hash, sleep.duration);
+    }
+    // Still connected: last thing before sleeping, as the board restarts once it's installed
+    if let Some(release) = board.release.take() {
+        update_firmware(&release);
+    }
+    info!("Deep sleep for {:?}", sleep.duration);
+    unsafe { esp_idf_sys::esp_deep_sleep(sleep.duration.as_micros() as u64) }
+}
+
+/// The drivers used by the refresh cycle
+#[cfg(feature = "deep_sleep")]
+struct Board<'a, 'p> {
+    wifi: BlockingWifi<EspWifi<'static>>,
+    settings: &'a Settings,
+    model: DisplayModel,
+    /// Kept to stay subscribed until the end of the cycle
+    mqtt: Option<(MqttClient, mpsc::Receiver<Received>)>,
+    /// The topics of the messages returned by `receive`, in the same order
+    received_topics: Vec<String>,
+    /// The message of the firmware control topic, handled at the end of the cycle
+    release: Option<Vec<u8>>,
+    last_payload_hash: Option<String>,
+    cache: Option<Cache>,
+    status: Status,
+    panel: &'a mut Panel<'p>,
+}
+
+#[cfg(feature = "deep_sleep")]
+impl Device for Board<'_, '_> {
+    type Error = anyhow::Error;
+
+    fn connect(&mut self) -> anyhow::Result<()> {
+        // The client of a previous cycle (an unverified firmware retries without sleeping) is closed first: the
+        // broker would drop one of the two sessions with the same client id
+        self.mqtt = None;
+        configure_wifi(&mut self.wifi, self.settings)?;
+        let (sender, receiver) = mpsc::channel::<Received>();
+        self.mqtt = Some((setup_mqtt_client(sender, self.settings, self.model)?, receiver));
+        Ok(())
+    }
+
+    fn receive(&mut self, timeout: Duration) -> Vec<Vec<u8>> {
+        let Some((_, receiver)) = &self.mqtt else {
+            return Vec::new();
+        };
+        let topics = display_topics(self.settings, self.model);
+        let deadline = Instant::now() + timeout;
+        let mut messages: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+        while messages.len() < topics.len() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match receiver.recv_timeout(remaining) {
+                Ok((topic, message)) if topic == firmware_topic() => self.release = Some(message),
+                Ok((topic, message)) => {
+                    messages.insert(topic, message);
                 }
-                draw_text(&mut display, format!(" {} ", &event.title).as_str(), 0, i, Alignment::Left);
-                draw_text(&mut display, format!(" {} ", &event.datetime).as_str(), DISPLAY_END, i, Alignment::Right);
-                i += FONT_HEIGHT;
+                // Show what has been received so far
+                Err(_) => break,
+            }
+        }
+        self.received_topics = messages.keys().cloned().collect();
+        messages.into_values().collect()
+    }
+
+    fn show(&mut self, messages: &[Vec<u8>]) -> anyhow::Result<Shown> {
+        // The topics whose payload is valid are still shown, with the error glyph
+        let mut contents = Vec::new();
+        let mut rejected = false;
+        for (topic, message) in self.received_topics.iter().zip(messages) {
+            match parse_message(message) {
+                Ok(content) => {
+                    self.last_payload_hash = Some(payload_hash(message));
+                    if content.is_some() {
+                        save_to_cache(self.cache.as_mut(), topic, message);
+                    }
+                    contents.extend(content);
+                }
+                Err(e) => {
+                    error!("Rejected payload on {}: {:#}", topic, e);
+                    if let Some((client, _)) = &mut self.mqtt {
+                        report_rejected(client, self.settings, topic, &e, message);
+                    }
+                    rejected = true;
+                }
+            }
+        }
+        let content = merge(&contents).ok_or_else(|| anyhow::anyhow!("no payload can be shown"))?;
+        let now = self.now();
+        self.status.synced_at = now;
+        self.status.rssi = rssi();
+        let payloads = cycle_hash(&self.received_topics, messages);
+        // Only accessed by the main thread
+        let unchanged = match (unsafe { ON_SCREEN }, now) {
+            (Some(shown), Some(now)) if !rejected && shown.payloads == payloads => {
+                same_labels(&content, shown.drawn_at, now)
+            }
+            _ => false,
+        };
+        if unchanged {
+            // The status bar keeps the time of the previous refresh
+            info!("Payloads unchanged, not refreshing");
+        } else {
+            let mut buffer = render_page(&content, 0, &self.status, self.model)?;
+            if rejected {
+                buffer = with_error_glyph(self.model, buffer);
+            }
+            unsafe { ON_SCREEN = None };
+            (self.panel)(PanelCommand::Show(&buffer))?;
+            unsafe {
+                ON_SCREEN = match (rejected, now) {
+                    (false, Some(now)) => Some(OnScreen { payloads, drawn_at: now }),
+                    _ => None,
+                }
+            };
+        }
+        Ok(Shown {
+            generated_at: content.generated_at,
+            // Also wake up to update the labels of the events
+            next_change_at: [content.next_change_at, next_label_change(&content)]
+                .into_iter()
+                .flatten()
+                .min(),
+        })
+    }
+
+    fn show_unreachable(&mut self, retry_in: Duration) -> anyhow::Result<()> {
+        // What's on screen isn't known after deep sleep: always a full screen notice
+        let notice = unreachable_screen(self.settings, self.model, None, retry_in);
+        unsafe { ON_SCREEN = None };
+        (self.panel)(PanelCommand::Show(&notice))
+    }
+
+    fn sleep_panel(&mut self) -> anyhow::Result<()> {
+        (self.panel)(PanelCommand::Sleep)
+    }
+
+    fn now(&self) -> Option<u64> {
+        now()
+    }
+}
+
+/// Current Unix timestamp (seconds), if the clock has been set by SNTP
+fn now() -> Option<u64> {
+    // The RTC keeps the time across deep sleep, but not across a power loss: anything before the firmware was written
+    // means that the clock hasn't been set
+    let now = std::time::UNIX_EPOCH.elapsed().ok()?.as_secs();
+    (now > 1688169600).then_some(now)
+}
+
+/// The time of the day of a timestamp in the TIMEZONE of the displays, eg. `10:42`
+fn local_time(timestamp: u64) -> String {
+    let time = timestamp as esp_idf_sys::time_t;
+    let mut tm: esp_idf_sys::tm = unsafe { mem::zeroed() };
+    unsafe { esp_idf_sys::localtime_r(&time, &mut tm) };
+    format!("{:02}:{:02}", tm.tm_hour, tm.tm_min)
+}
+
+/// Signal strength of the access point the board is connected to, in dBm
+fn rssi() -> Option<i8> {
+    let mut info: esp_idf_sys::wifi_ap_record_t = unsafe { mem::zeroed() };
+    let result = unsafe { esp_idf_sys::esp_wifi_sta_get_ap_info(&mut info) };
+    (result == esp_idf_sys::ESP_OK).then_some(info.rssi)
+}
+
+/// The style of the layout rendered by the board (see the profile of its panel): with a status bar, unless the server
+/// renders the screen
+fn style(model: DisplayModel) -> Style {
+    let mut style = profile(model).style();
+    style.status_bar = cfg!(not(feature = "server_render"));
+    style
+}
+
+/// What the board is currently showing, kept to render the other pages
+#[cfg(not(feature = "server_render"))]
+type Content = Envelope;
+#[cfg(feature = "server_render")]
+type Content = Frame;
+
+/// Parses a message received from the MQTT topic, or returns None if it should be ignored
+#[cfg(not(feature = "server_render"))]
+fn parse_message(message: &[u8]) -> anyhow::Result<Option<Content>> {
+    let envelope = match Envelope::decode(message, PAYLOAD_ENCODING) {
+        Err(PayloadError::UnsupportedVersion(version)) => {
+            // Keep showing the last screen until this firmware gets updated
+            warn!("Ignoring payload with unsupported schema version {}", version);
+            return Ok(None);
+        }
+        envelope => envelope?,
+    };
+    info!("Payload generated at {} for {:?} {}", envelope.generated_at, envelope.topic_kind, envelope.building.id);
+    // There's no clock on the board yet: generated_at is the best estimate of the current time for a fresh payload
+    info!("Next content change in {:?}", envelope.valid_for(envelope.generated_at, MAX_SLEEP));
+    Ok(Some(envelope))
+}
+
+/// Merges the contents of the display topics into the screen to show
+#[cfg(not(feature = "server_render"))]
+fn merge(envelopes: &[Content]) -> Option<Content> {
+    identity::merge(envelopes)
+}
+
+/// The content as it should be shown now: without the events that are over, and with relative labels. As received
+/// until the clock is set
+#[cfg(not(feature = "server_render"))]
+fn current(envelope: &Content) -> Content {
+    match now() {
+        Some(now) => AGENDA.at(envelope, now),
+        None => envelope.clone(),
+    }
+}
+
+/// When the content shown is due to change, see `current`
+#[cfg(not(feature = "server_render"))]
+fn next_label_change(envelope: &Content) -> Option<u64> {
+    AGENDA.next_change(envelope, now()?)
+}
+
+/// Whether the events of the content are shown the same at `now` as at `drawn_at`, see `current`
+#[cfg(all(feature = "deep_sleep", not(feature = "server_render")))]
+fn same_labels(envelope: &Content, drawn_at: u64, now: u64) -> bool {
+    AGENDA.at(envelope, drawn_at) == AGENDA.at(envelope, now)
+}
+
+#[cfg(not(feature = "server_render"))]
+fn page_count(envelope: &Content, model: DisplayModel) -> usize {
+    display_layout::page_count(&current(envelope), &style(model), profile(model).size())
+}
+
+/// The framebuffer of a page, ready to be sent to the panel
+#[cfg(not(feature = "server_render"))]
+fn render_page(
+    envelope: &Content,
+    page: usize,
+    status: &Status,
+    model: DisplayModel,
+) -> anyhow::Result<Vec<u8>> {
+    let style = style(model);
+    let mut framebuffer = Framebuffer::new(model);
+    let envelope = current(envelope);
+    if display_layout::is_idle(&envelope) {
+        // Also when the last events are over: the place stays on screen, with the time of the last update
+        let synced_at = status.synced_at.map(local_time);
+        display_layout::render_idle(&envelope, synced_at.as_deref(), &style, &mut framebuffer)?;
+    } else {
+        display_layout::render(&envelope, &style, page, &mut framebuffer)?;
+    }
+    let status_bar = status.bar(now(), STALE_AFTER, local_time);
+    draw_status_bar(&status_bar, &style, &mut framebuffer)?;
+    Ok(framebuffer.into_buffer())
+}
+
+/// Parses a message received from the MQTT topic, or returns None if it should be ignored
+#[cfg(feature = "server_render")]
+fn parse_message(message: &[u8]) -> anyhow::Result<Option<Content>> {
+    let frame = match Frame::decode(message) {
+        Err(PayloadError::UnsupportedVersion(version)) => {
+            warn!("Ignoring frame with unsupported schema version {}", version);
+            return Ok(None);
+        }
+        frame => frame?,
+    };
+    info!("Frame generated at {}", frame.generated_at);
+    Ok(Some(frame))
+}
+
+/// Merges the contents of the display topics into the screen to show
+#[cfg(feature = "server_render")]
+fn merge(frames: &[Content]) -> Option<Content> {
+    // Only a single topic is subscribed to
+    frames.last().cloned()
+}
+
+/// Frames can't be updated on the board: they only change with a new payload
+#[cfg(feature = "server_render")]
+fn next_label_change(_frame: &Content) -> Option<u64> {
+    None
+}
+
+/// Frames are shown as received
+#[cfg(all(feature = "deep_sleep", feature = "server_render"))]
+fn same_labels(_frame: &Content, _drawn_at: u64, _now: u64) -> bool {
+    true
+}
+
+#[cfg(feature = "server_render")]
+fn page_count(frame: &Content, _model: DisplayModel) -> usize {
+    frame.page_count()
+}
+
+/// The framebuffer of a page, ready to be sent to the panel
+#[cfg(feature = "server_render")]
+fn render_page(
+    frame: &Content,
+    page: usize,
+    _status: &Status,
+    model: DisplayModel,
+) -> anyhow::Result<Vec<u8>> {
+    // Already rendered by the server in the panel's native layout, without status bar
+    Ok(frame.buffer(model, page)?)
+}
+
+/// Connects to the first access point of the settings that can be reached, in order of preference
+fn configure_wifi(wifi: &mut BlockingWifi<EspWifi>, settings: &Settings) -> Result<(), EspError> {
+    if !wifi.is_started()? {
+        wifi.start()?;
+        info!("Wifi started!");
+    }
+
+    let mut result = Err(EspError::from_infallible::<{ esp_idf_sys::ESP_ERR_WIFI_SSID }>());
+    for access_point in settings.access_points() {
+        info!("Connecting to {}...", access_point.ssid);
+        result = connect_to(wifi, &access_point);
+        match &result {
+            Ok(()) => {
+                info!("Wifi ready!");
+                break;
+            }
+            Err(e) => {
+                warn!("Can't connect to {}: {}", access_point.ssid, e);
+                let _ = wifi.disconnect();
             }
-            epd.update_frame(&mut device, display.buffer(), &mut delay)?;
-            epd.display_frame(&mut device, &mut delay)?;
         }
     }
+    result
 }
 
-fn configure_wifi(wifi: &mut BlockingWifi<EspWifi>) -> Result<(), EspError> {
+fn connect_to(wifi: &mut BlockingWifi<EspWifi>, access_point: &AccessPoint) -> Result<(), EspError> {
+    let auth_method = match access_point.security() {
+        Security::Open => AuthMethod::None,
+        // The weakest accepted: WPA3 personal and WPA2/WPA3 transition networks are accepted as well
+        Security::Personal => AuthMethod::WPA2Personal,
+    };
     wifi.set_configuration(&Configuration::Client(ClientConfiguration {
-        ssid: WIFI_SSID.into(),
-        password: WIFI_PASS.into(),
-        auth_method: AuthMethod::None,
+        ssid: access_point.ssid.as_str().into(),
+        password: access_point.pass.as_str().into(),
+        auth_method,
         ..Default::default()
     }))?;
-    wifi.start()?;
-    info!("Wifi started!");
 
     wifi.connect()?;
     info!("Wifi connected!");
 
-    wifi.wait_netif_up()?;
-    info!("Wifi ready!");
-
-    Ok(())
+    wifi.wait_netif_up()
 }
 
 fn setup_mqtt_client(
-    sender: Sender<String>,
-) -> Result<EspMqttClient<ConnState<MessageImpl, EspError>>, EspError> {
+    sender: Sender<Received>,
+    settings: &Settings,
+    model: DisplayModel,
+) -> Result<MqttClient, EspError> {
     info!("About to start MQTT client");
 
     let mut conf = MqttClientConfiguration {
-        client_id: Some(MQTT_CLIENT_ID),
+        client_id: Some(&settings.mqtt_client_id),
         crt_bundle_attach: Some(esp_idf_sys::esp_crt_bundle_attach),
         ..Default::default()
     };
@@ -227,7 +1194,7 @@ fn setup_mqtt_client(
         conf.private_key = Some(private_key);
     }
 
-    let (mut client, mut connection) = EspMqttClient::new_with_conn(MQTT_ENDPOINT, &conf)?;
+    let (mut client, mut connection) = EspMqttClient::new_with_conn(&settings.mqtt_endpoint, &conf)?;
 
     info!("MQTT client started!");
 
@@ -241,10 +1208,14 @@ fn setup_mqtt_client(
                 Ok(msg) => {
                     info!("MQTT Message: {:?}", msg);
                     if let Event::Received(msg) = msg {
-                        let parsed_string = String::from_utf8(msg.data().to_vec());
-                        if let Ok(parsed_string) = parsed_string {
-                            info!("Parsed MQTT message: {:?}", parsed_string);
-                            sender.send(parsed_string).unwrap();
+                        // Payloads might be binary depending on PAYLOAD_ENCODING, they're decoded in the main thread.
+                        // The topic tells which of the display topics the payload replaces
+                        if let Some(topic) = msg.topic() {
+                            if sender.send((topic.to_string(), msg.data().to_vec())).is_err() {
+                                // Only when the main thread has dropped the client
+                                warn!("No one is waiting for the MQTT messages anymore");
+                                break;
+                            }
                         }
                     }
                 }
@@ -254,9 +1225,15 @@ fn setup_mqtt_client(
         info!("MQTT connection loop exit");
     });
 
-    client.subscribe(MQTT_TOPIC_NAME, QoS::AtMostOnce)?;
+    let mut topics = display_topics(settings, model);
+    if firmware_public_key().is_some() {
+        topics.push(firmware_topic());
+    }
+    for topic in &topics {
+        client.subscribe(topic, QoS::AtMostOnce)?;
+    }
 
-    info!("Subscribed to all topics ({})", MQTT_TOPIC_NAME);
+    info!("Subscribed to all topics ({})", topics.join(", "));
 
     // Delay::delay_ms(1000);
     // // This will be the first message appearing on the screen
@@ -292,40 +1269,3 @@ fn convert_certificate(mut certificate_bytes: Vec<u8>) -> X509<'static> {
     // return the certificate file in the correct format
     X509::pem_until_nul(certificate_slice)
 }
-
-#[cfg(feature = "epd2in9_v2")]
-pub fn draw_text(display: &mut EpdDisplay, text: &str, x: i32, y: i32, align: Alignment) {
-    display.set_rotation(DisplayRotation::Rotate90);
-    let style = MonoTextStyleBuilder::new()
-        .font(&embedded_graphics::mono_font::ascii::FONT_7X13_BOLD)
-        .text_color(Color::Black)
-        .background_color(Color::White)
-        .build();
-
-    let text_style = TextStyleBuilder::new().baseline(Baseline::Top).alignment(align).build();
-
-    let _ = Text::with_text_style(text, Point::new(x, y), style, text_style).draw(display);
-}
-
-#[cfg(feature = "epd5in83_v2")]
-pub fn draw_text(display: &mut EpdDisplay, text: &str, x: i32, y: i32, align: Alignment) {
-    let style = MonoTextStyleBuilder::new()
-        .font(&embedded_graphics::mono_font::ascii::FONT_10X20)
-        .text_color(Color::Black)
-        .background_color(Color::White)
-        .build();
-
-    let text_style = TextStyleBuilder::new().baseline(Baseline::Top).alignment(align).build();
-
-    let _ = Text::with_text_style(text, Point::new(x, y), style, text_style).draw(display);
-}
-
-#[derive(Deserialize)]
-pub struct SEvent {
-    pub id: String,
-    pub title: String,
-    pub timestamp: u64,
-    pub datetime: String,
-    pub building: String,
-    pub room: String,
-}
@@ -0,0 +1,118 @@
+use anyhow::bail;
+use display_logic::ota::{self, ImageCheck};
+use display_payload::Release;
+use embedded_svc::{
+    http::{client::Connection, Method},
+    io::Read,
+    ota::SlotState,
+};
+use esp_idf_svc::{
+    http::client::{Configuration, EspHttpConnection},
+    ota::EspOta,
+};
+use log::*;
+
+use crate::FIRMWARE_VARIANT;
+
+const CHUNK_SIZE: usize = 4096;
+
+/// Downloads the image of the release into the next OTA partition and checks it while it's written: the board
+/// restarts on the new firmware if it's the one that has been signed, and keeps running the current one otherwise
+pub fn install(release: &Release, public_key: &[u8; 32]) -> anyhow::Result<()> {
+    info!("Installing firmware {} from {}", release.version, release.url);
+    let mut ota = EspOta::new()?;
+    let mut update = ota.initiate_update()?;
+    let mut check = ImageCheck::new();
+    let downloaded = download(&release.url, |chunk| {
+        check.update(chunk);
+        update.write(chunk)?;
+        Ok(())
+    });
+    let verified = downloaded.and_then(|()| {
+        info!("Downloaded {} bytes, verifying...", check.len());
+        Ok(check.verify(release, FIRMWARE_VARIANT, public_key)?)
+    });
+    match verified {
+        Ok(()) => {
+            update.complete()?;
+            info!("Firmware {} installed, restarting", release.version);
+            unsafe { esp_idf_sys::esp_restart() }
+        }
+        Err(e) => {
+            // The partition is left unbootable
+            let _ = update.abort();
+            Err(e)
+        }
+    }
+}
+
+/// Streams the body of `url` (HTTPS, or plain HTTP for a local server) to `on_chunk`
+fn download(url: &str, mut on_chunk: impl FnMut(&[u8]) -> anyhow::Result<()>) -> anyhow::Result<()> {
+    let mut connection = EspHttpConnection::new(&Configuration {
+        crt_bundle_attach: Some(esp_idf_sys::esp_crt_bundle_attach),
+        buffer_size: Some(CHUNK_SIZE),
+        ..Default::default()
+    })?;
+    connection.initiate_request(Method::Get, url, &[])?;
+    connection.initiate_response()?;
+    if connection.status() != 200 {
+        bail!("HTTP {} downloading {}", connection.status(), url);
+    }
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        match connection.read(&mut buffer)? {
+            0 => return Ok(()),
+            read => on_chunk(&buffer[..read])?,
+        }
+    }
+}
+
+/// Whether the running firmware has just been installed, and hasn't proven yet that it works: until it's marked
+/// valid, the bootloader rolls back to the previous one on the next restart
+pub fn is_unverified() -> bool {
+    match EspOta::new().and_then(|ota| ota.get_running_slot()) {
+        Ok(slot) => slot.state == SlotState::Unverified,
+        Err(e) => {
+            warn!("Can't read the state of the running firmware: {}", e);
+            false
+        }
+    }
+}
+
+/// The health check of a new firmware passed: keep it
+pub fn mark_valid() {
+    match EspOta::new().and_then(|mut ota| ota.mark_running_slot_valid()) {
+        Ok(()) => info!("Firmware {} marked as valid", env!("CARGO_PKG_VERSION")),
+        Err(e) => warn!("Can't mark the firmware as valid: {}", e),
+    }
+}
+
+/// The health check of a new firmware failed: go back to the previous one
+pub fn rollback() -> ! {
+    error!("Firmware {} failed its health check, rolling back", env!("CARGO_PKG_VERSION"));
+    if let Ok(mut ota) = EspOta::new() {
+        let e = ota.mark_running_slot_invalid_and_reboot();
+        error!("Can't roll back: {:?}", e);
+    }
+    unsafe { esp_idf_sys::esp_restart() }
+}
+
+/// The version of the last firmware that has been rolled back, not to install it again
+fn rolled_back_version() -> Option<String> {
+    let slot = EspOta::new().and_then(|ota| ota.get_last_invalid_slot()).ok()??;
+    Some(slot.firmware?.version.to_string())
+}
+
+/// Installs the release announced on the control topic, if it's newer than the running firmware
+pub fn handle_release(message: &[u8], public_key: &[u8; 32]) -> anyhow::Result<()> {
+    let release = Release::decode(message)?;
+    if !ota::should_update(&release, env!("CARGO_PKG_VERSION"))? {
+        info!("Firmware {} is up to date (release {})", env!("CARGO_PKG_VERSION"), release.version);
+        return Ok(());
+    }
+    if rolled_back_version().as_deref() == Some(release.version.as_str()) {
+        warn!("Firmware {} has been rolled back, not installing it again", release.version);
+        return Ok(());
+    }
+    install(&release, public_key)
+}
@@ -0,0 +1,38 @@
+import argparse
+import hashlib
+import json
+import sys
+
+from cryptography.hazmat.primitives import serialization
+from cryptography.hazmat.primitives.asymmetric.ed25519 import Ed25519PrivateKey
+
+# Signs a firmware image for the over-the-air updates of rmqtt, printing the release to publish (retained) on
+# control/firmware/<variant>. See the OTA section of the rmqtt README
+parser = argparse.ArgumentParser()
+parser.add_argument("-k", "--key", help = "PEM file of the Ed25519 private key", required=True)
+parser.add_argument("--generate-key", help = "Create the private key and print the public one", action="store_true")
+parser.add_argument("-i", "--image", help = "Firmware image (espflash save-image)")
+parser.add_argument("-v", "--version", help = "Version of the firmware, as in rmqtt/Cargo.toml")
+parser.add_argument("-u", "--url", help = "Where the displays download the image from")
+parser.add_argument("--variant", help = "Firmware variant the release is for (FIRMWARE_VARIANT in rmqtt)", default="display")
+
+args = parser.parse_args()
+
+if args.generate_key:
+    key = Ed25519PrivateKey.generate()
+    with open(args.key, "wb") as file:
+        file.write(key.private_bytes(serialization.Encoding.PEM, serialization.PrivateFormat.PKCS8, serialization.NoEncryption()))
+else:
+    if not (args.image and args.version and args.url):
+        parser.error("--image, --version and --url are required to sign a release")
+    with open(args.key, "rb") as file:
+        key = serialization.load_pem_private_key(file.read(), password=None)
+    with open(args.image, "rb") as file:
+        sha256 = hashlib.sha256(file.read()).digest()
+    # The hash is signed, not the image: the board checks it while the image is being downloaded. The variant and the
+    # version are signed with it (see Release::signed_message), so that an old image can't be announced as a new one
+    message = f"{args.variant}\n{args.version}\n{sha256.hex()}".encode()
+    print(json.dumps({"version": args.version, "url": args.url, "sha256": sha256.hex(), "signature": key.sign(message).hex()}))
+
+public_key = key.public_key().public_bytes(serialization.Encoding.Raw, serialization.PublicFormat.Raw)
+print(f"Public key (FIRMWARE_PUBLIC_KEY): {public_key.hex()}", file=sys.stderr)
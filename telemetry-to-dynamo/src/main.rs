@@ -0,0 +1,160 @@
+use std::{collections::HashMap, time::{SystemTime, UNIX_EPOCH}};
+
+use aws_sdk_dynamodb::types::{AttributeValue, ReturnValue};
+use display_payload::Heartbeat;
+use lambda_runtime::{run, service_fn, Error, LambdaEvent};
+use serde_json::{json, Value};
+use tracing::log::{info, warn};
+
+const AWS_ENDPOINT_URL: &str = "http://localhost:4566";
+const DYNAMODB_HEARTBEATS_TABLE: &str = "display_heartbeats";
+// Webhook of the Discord channel where the alerts are posted (Server settings > Integrations > Webhooks)
+const DISCORD_WEBHOOK_URL: &str = "";
+// After how many missed heartbeats a display is considered down. Displays in deep sleep mode can wake up a bit late
+const MISSED_HEARTBEATS_ALERT: u64 = 3;
+const FIELD_CLIENT_ID: &str = "client_id";
+const FIELD_RECEIVED_AT: &str = "received_at";
+const FIELD_HEARTBEAT: &str = "heartbeat";
+const FIELD_ALERTED: &str = "alerted";
+
+/// The last heartbeat received from a display, as stored in DynamoDB
+struct Record {
+    heartbeat: Heartbeat,
+    received_at: u64,
+    /// Whether an alert has been posted since this heartbeat
+    alerted: bool,
+}
+
+impl Record {
+    fn from_hashmap(map: &HashMap<String, AttributeValue>) -> Option<Self> {
+        let heartbeat = map.get(FIELD_HEARTBEAT)?.as_s().ok()?;
+        Some(Self {
+            heartbeat: Heartbeat::decode(heartbeat.as_bytes()).ok()?,
+            received_at: map.get(FIELD_RECEIVED_AT)?.as_n().ok()?.parse().ok()?,
+            alerted: map.get(FIELD_ALERTED).and_then(|alerted| alerted.as_bool().ok()).copied().unwrap_or(false),
+        })
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// Keeps the last heartbeat of the display. The fields are also stored on their own, to be queried from the console
+async fn store(client: &aws_sdk_dynamodb::Client, heartbeat: Heartbeat) -> Result<(), Error> {
+    info!("Heartbeat of {}: {:?}", heartbeat.client_id, heartbeat);
+    let mut request = client.put_item()
+        .table_name(DYNAMODB_HEARTBEATS_TABLE)
+        .item(FIELD_CLIENT_ID, AttributeValue::S(heartbeat.client_id.clone()))
+        .item(FIELD_RECEIVED_AT, AttributeValue::N(now().to_string()))
+        .item(FIELD_HEARTBEAT, AttributeValue::S(String::from_utf8(heartbeat.encode()?)?))
+        .item(FIELD_ALERTED, AttributeValue::Bool(false))
+        .item("firmware_version", AttributeValue::S(heartbeat.firmware_version.clone()))
+        .item("uptime", AttributeValue::N(heartbeat.uptime.to_string()))
+        .return_values(ReturnValue::AllOld);
+    if let Some(battery) = heartbeat.battery {
+        request = request.item("battery", AttributeValue::N(battery.to_string()));
+    }
+    if let Some(rssi) = heartbeat.rssi {
+        request = request.item("rssi", AttributeValue::N(rssi.to_string()));
+    }
+    if let Some(hash) = &heartbeat.last_payload_hash {
+        request = request.item("last_payload_hash", AttributeValue::S(hash.clone()));
+    }
+    let output = request.send().await?;
+    let previous = output.attributes().and_then(Record::from_hashmap);
+    if previous.map_or(false, |previous| previous.alerted) {
+        post_alert(&format!("Il display {} è di nuovo online.", heartbeat.client_id)).await?;
+    }
+    Ok(())
+}
+
+/// Every record of the heartbeats table: a scan returns them in pages of at most 1MB
+async fn records(client: &aws_sdk_dynamodb::Client) -> Result<Vec<Record>, Error> {
+    let mut records = Vec::new();
+    let mut start_key = None;
+    loop {
+        let output = client.scan()
+            .table_name(DYNAMODB_HEARTBEATS_TABLE)
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await?;
+        records.extend(output.items().unwrap_or_default().iter().filter_map(Record::from_hashmap));
+        match output.last_evaluated_key() {
+            Some(key) => start_key = Some(key.clone()),
+            None => return Ok(records),
+        }
+    }
+}
+
+/// Posts an alert for every display that missed too many heartbeats, once per outage
+async fn check(client: &aws_sdk_dynamodb::Client) -> Result<(), Error> {
+    let now = now();
+    for record in records(client).await? {
+        let missed = record.heartbeat.missed(record.received_at, now);
+        if missed < MISSED_HEARTBEATS_ALERT || record.alerted {
+            continue;
+        }
+        let heartbeat = &record.heartbeat;
+        warn!("{} missed {} heartbeats", heartbeat.client_id, missed);
+        let battery = heartbeat.battery.map_or("n/d".to_string(), |battery| format!("{}%", battery));
+        let rssi = heartbeat.rssi.map_or("n/d".to_string(), |rssi| format!("{} dBm", rssi));
+        post_alert(&format!(
+            "Il display {} non dà segni di vita da {} minuti (firmware {}, batteria {}, segnale {}).",
+            heartbeat.client_id,
+            now.saturating_sub(record.received_at) / 60,
+            heartbeat.firmware_version,
+            battery,
+            rssi,
+        )).await?;
+        client.update_item()
+            .table_name(DYNAMODB_HEARTBEATS_TABLE)
+            .key(FIELD_CLIENT_ID, AttributeValue::S(heartbeat.client_id.clone()))
+            .update_expression(format!("SET {} = :alerted", FIELD_ALERTED))
+            .expression_attribute_values(":alerted", AttributeValue::Bool(true))
+            .send()
+            .await?;
+    }
+    Ok(())
+}
+
+async fn post_alert(content: &str) -> Result<(), Error> {
+    println!("Alert: {}", content);
+    if DISCORD_WEBHOOK_URL.is_empty() {
+        return Ok(());
+    }
+    reqwest::Client::new()
+        .post(DISCORD_WEBHOOK_URL)
+        .json(&json!({ "content": content }))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Invoked with either a heartbeat, by the AWS IoT rule of the telemetry topics, or a scheduled event, to look for the
+/// displays that stopped sending them
+async fn function_handler(event: LambdaEvent<Value>) -> Result<Value, Error> {
+    let config = aws_config::from_env().endpoint_url(AWS_ENDPOINT_URL).load().await;
+    let client = aws_sdk_dynamodb::Client::new(&config);
+    if event.payload.get("detail-type").is_some() {
+        check(&client).await?;
+    } else {
+        let heartbeat = Heartbeat::decode(&serde_json::to_vec(&event.payload)?)?;
+        store(&client, heartbeat).await?;
+    }
+    Ok(json!({"res":"ok"}))
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        // disable printing the name of the module in every log line.
+        .with_target(false)
+        // disabling time is handy because CloudWatch will add the ingestion time.
+        .without_time()
+        .init();
+
+    run(service_fn(function_handler)).await
+}